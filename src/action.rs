@@ -0,0 +1,192 @@
+use termion::event::Key;
+use crate::finder;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum Action {
+    Quit,
+    Save,
+    Open,
+    Reload,
+    FindFile,
+    CloseBuffer,
+    SwitchBuffer,
+    NextBuffer,
+    PreviousBuffer,
+    ToggleExplorer,
+    Search,
+    SearchProject,
+    ReplaceInProject,
+    GoTo,
+    JumpToBracket,
+    CommandLine,
+    CommandPalette,
+    SetFiletype,
+    ToggleLineComment,
+    ToggleBlockComment,
+    DuplicateLines,
+    DeleteLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    JoinLines,
+    InsertLineBelow,
+    InsertLineAbove,
+    SortLines,
+    SortLinesUnique,
+    WordLeft,
+    WordRight,
+    DeleteWordBackward,
+    DeleteWordForward,
+    ToggleSubwordMotion,
+    SplitVertical,
+    SplitHorizontal,
+    CloseWindow,
+    FocusLeft,
+    FocusDown,
+    FocusUp,
+    FocusRight,
+    GrowWindow,
+    ShrinkWindow,
+}
+
+pub struct Entry {
+    pub action: Action,
+    pub name: &'static str,
+    pub description: &'static str,
+    // keys that run the action, the first one being shown as its binding
+    pub keys: &'static [Key],
+}
+
+impl Entry {
+    // how the first key is written, empty when nothing runs the action
+    pub fn binding(&self) -> String {
+        self.keys.first().map(|key| key_name(*key)).unwrap_or_default()
+    }
+}
+
+// every action the editor has, in the order the palette lists them
+pub const ACTIONS: &[Entry] = &[
+    Entry { action: Action::Quit, name: "quit", description: "quit, asking again while buffers are unsaved", keys: &[Key::Ctrl('c')] },
+    Entry { action: Action::Save, name: "save", description: "write the buffer, asking for a name when it has none", keys: &[Key::Ctrl('s')] },
+    Entry { action: Action::Open, name: "open file", description: "open a file by its path", keys: &[Key::Ctrl('o')] },
+    Entry { action: Action::Reload, name: "reload file", description: "read the buffer's file again, unless it has unsaved changes", keys: &[] },
+    Entry { action: Action::FindFile, name: "find file", description: "fuzzy find a project file, with a preview", keys: &[Key::Ctrl('p')] },
+    Entry { action: Action::CloseBuffer, name: "close buffer", description: "close the buffer, asking first when it is unsaved", keys: &[Key::Ctrl('w')] },
+    Entry { action: Action::SwitchBuffer, name: "switch buffer", description: "pick an open buffer by name", keys: &[Key::Ctrl('e')] },
+    Entry { action: Action::NextBuffer, name: "next buffer", description: "show the next open buffer", keys: &[Key::AltRight] },
+    Entry { action: Action::PreviousBuffer, name: "previous buffer", description: "show the previous open buffer", keys: &[Key::AltLeft] },
+    Entry { action: Action::ToggleExplorer, name: "toggle file explorer", description: "show, focus or hide the file tree sidebar", keys: &[Key::Ctrl('n')] },
+    Entry { action: Action::Search, name: "search", description: "search the buffer incrementally", keys: &[Key::Ctrl('f')] },
+    Entry { action: Action::SearchProject, name: "search project", description: "search every project file, /regex/ or literal", keys: &[Key::Alt('f')] },
+    Entry { action: Action::ReplaceInProject, name: "replace in project", description: "replace across project files with a preview", keys: &[Key::Alt('r')] },
+    Entry { action: Action::GoTo, name: "go to line", description: "move to a line, line:col, relative line or percentage", keys: &[Key::Ctrl('g')] },
+    Entry { action: Action::JumpToBracket, name: "jump to matching bracket", description: "move to the bracket pairing the one at or around the cursor", keys: &[Key::Ctrl('b')] },
+    Entry { action: Action::CommandLine, name: "command line", description: "run an ex-style command", keys: &[Key::Alt(':'), Key::Alt(';')] },
+    Entry { action: Action::CommandPalette, name: "command palette", description: "list every action and run one", keys: &[Key::Alt('x')] },
+    Entry { action: Action::SetFiletype, name: "set filetype", description: "pick the language the buffer is highlighted as", keys: &[Key::Ctrl('t')] },
+    // Ctrl-/ arrives as Ctrl-7
+    Entry { action: Action::ToggleLineComment, name: "toggle line comment", description: "comment or uncomment the selected lines", keys: &[Key::Ctrl('7')] },
+    Entry { action: Action::ToggleBlockComment, name: "toggle block comment", description: "wrap the selection in a block comment or unwrap it", keys: &[Key::Alt('/')] },
+    Entry { action: Action::DuplicateLines, name: "duplicate lines", description: "copy the selected lines below themselves", keys: &[Key::Ctrl('d')] },
+    Entry { action: Action::DeleteLines, name: "delete lines", description: "delete the selected lines", keys: &[Key::Ctrl('k')] },
+    Entry { action: Action::MoveLinesUp, name: "move lines up", description: "swap the selected lines with the one above", keys: &[Key::AltUp] },
+    Entry { action: Action::MoveLinesDown, name: "move lines down", description: "swap the selected lines with the one below", keys: &[Key::AltDown] },
+    Entry { action: Action::JoinLines, name: "join lines", description: "append the next line to the current one", keys: &[Key::Alt('j')] },
+    Entry { action: Action::InsertLineBelow, name: "insert line below", description: "add a blank line below the cursor", keys: &[Key::Alt('o')] },
+    Entry { action: Action::InsertLineAbove, name: "insert line above", description: "add a blank line above the cursor", keys: &[Key::Alt('O')] },
+    Entry { action: Action::SortLines, name: "sort lines", description: "sort the selected lines", keys: &[Key::Alt('s')] },
+    Entry { action: Action::SortLinesUnique, name: "sort lines unique", description: "sort the selected lines, dropping duplicates", keys: &[Key::Alt('u')] },
+    Entry { action: Action::WordLeft, name: "word left", description: "move to the previous word start", keys: &[Key::CtrlLeft] },
+    Entry { action: Action::WordRight, name: "word right", description: "move to the next word end", keys: &[Key::CtrlRight] },
+    // many terminals send Ctrl-h for a plain Backspace, so only Alt-Backspace deletes a word
+    Entry { action: Action::DeleteWordBackward, name: "delete word backward", description: "delete up to the previous word start", keys: &[Key::Alt('\x7f')] },
+    Entry { action: Action::DeleteWordForward, name: "delete word forward", description: "delete up to the next word end", keys: &[Key::Alt('d')] },
+    Entry { action: Action::ToggleSubwordMotion, name: "toggle sub-word motion", description: "stop word motions inside camelCase and snake_case", keys: &[Key::Alt('w')] },
+    Entry { action: Action::SplitVertical, name: "split vertically", description: "show the buffer in a new window on the right", keys: &[Key::Alt('v')] },
+    Entry { action: Action::SplitHorizontal, name: "split horizontally", description: "show the buffer in a new window below", keys: &[Key::Alt('h')] },
+    Entry { action: Action::CloseWindow, name: "close window", description: "close the focused window", keys: &[Key::Alt('q')] },
+    Entry { action: Action::FocusLeft, name: "focus window left", description: "focus the window to the left", keys: &[Key::Alt('H')] },
+    Entry { action: Action::FocusDown, name: "focus window below", description: "focus the window below", keys: &[Key::Alt('J')] },
+    Entry { action: Action::FocusUp, name: "focus window above", description: "focus the window above", keys: &[Key::Alt('K')] },
+    Entry { action: Action::FocusRight, name: "focus window right", description: "focus the window to the right", keys: &[Key::Alt('L')] },
+    Entry { action: Action::GrowWindow, name: "grow window", description: "give the focused window more room", keys: &[Key::Alt('+'), Key::Alt('=')] },
+    Entry { action: Action::ShrinkWindow, name: "shrink window", description: "give the focused window less room", keys: &[Key::Alt('-')] },
+];
+
+pub fn for_key(key: Key) -> Option<Action> {
+    ACTIONS.iter().find(|entry| entry.keys.contains(&key)).map(|entry| entry.action)
+}
+
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Ctrl('7') => "Ctrl-/".to_string(),
+        Key::Alt('\x7f') => "Alt-Backspace".to_string(),
+        Key::Ctrl(c) => format!("Ctrl-{c}"),
+        Key::Alt(c) => format!("Alt-{c}"),
+        Key::CtrlLeft => "Ctrl-Left".to_string(),
+        Key::CtrlRight => "Ctrl-Right".to_string(),
+        Key::AltLeft => "Alt-Left".to_string(),
+        Key::AltRight => "Alt-Right".to_string(),
+        Key::AltUp => "Alt-Up".to_string(),
+        Key::AltDown => "Alt-Down".to_string(),
+        Key::Char(c) => c.to_string(),
+        _ => String::new(),
+    }
+}
+
+pub struct Match {
+    pub entry: &'static Entry,
+    // char indices of the entry's name the query matched
+    pub positions: Vec<usize>,
+}
+
+// the actions whose names match a query, best first
+pub struct Palette {
+    query: String,
+    matches: Vec<Match>,
+    selected: usize,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        let mut palette = Self { query: String::new(), matches: Vec::new(), selected: 0 };
+        palette.rank();
+        palette
+    }
+
+    pub fn set_query(&mut self, query: &str) {
+        if query != self.query {
+            query.clone_into(&mut self.query);
+            self.selected = 0;
+            self.rank();
+        }
+    }
+
+    fn rank(&mut self) {
+        let mut matches: Vec<(i64, Match)> = ACTIONS.iter()
+            .filter_map(|entry| finder::score(&self.query, entry.name).map(|(score, positions)| (score, Match { entry, positions })))
+            .collect();
+        // ties keep the registry order
+        matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+        self.matches = matches.into_iter().map(|(_, found)| found).collect();
+    }
+
+    pub fn move_selection(&mut self, up: bool) {
+        self.selected = if up {
+            self.selected.saturating_sub(1)
+        } else {
+            self.selected.saturating_add(1).min(self.matches.len().saturating_sub(1))
+        };
+    }
+
+    pub fn matches(&self) -> &[Match] {
+        &self.matches
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_action(&self) -> Option<Action> {
+        self.matches.get(self.selected).map(|found| found.entry.action)
+    }
+}
@@ -0,0 +1,276 @@
+use std::fs;
+use std::path::Path;
+
+// names the command line completes
+pub const COMMANDS: [&str; 16] = [
+    "write", "wq", "w", "x", "quit", "q", "q!", "edit", "e", "e!", "read", "r", "set", "delete", "d", "substitute",
+];
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Address {
+    // counting from 1
+    Line(usize),
+    Current,
+    Last,
+}
+
+// first and last line, both included
+pub type AddressRange = (Address, Address);
+
+pub enum Command {
+    Write(Option<String>),
+    WriteQuit,
+    Quit { force: bool },
+    Edit { path: Option<String>, force: bool },
+    Read(String),
+    Set(String),
+    Delete,
+    Substitute { pattern: String, replacement: String, global: bool },
+    // a range on its own moves to its last line
+    GoTo,
+}
+
+pub struct Parsed {
+    pub range: Option<AddressRange>,
+    pub command: Command,
+}
+
+// `[range]command [argument]`, the range being `%`, or one or two of
+// `N`, `.` and `$` separated by a comma
+pub fn parse(input: &str) -> Result<Parsed, String> {
+    let input = input.trim();
+    let (range, rest) = parse_range(input)?;
+    let rest = rest.trim_start();
+    // `s` takes its delimiter right after the name
+    let delimited = rest.starts_with('s') && rest.chars().nth(1).is_some_and(|c| !c.is_alphanumeric() && !c.is_whitespace());
+    let (name, argument) = if delimited {
+        rest.split_at(1)
+    } else {
+        rest.split_once(char::is_whitespace).unwrap_or((rest, ""))
+    };
+    let argument = argument.trim();
+    let path = || (!argument.is_empty()).then(|| argument.to_string());
+    let command = match name {
+        "" if range.is_some() => Command::GoTo,
+        "w" | "write" => Command::Write(path()),
+        "wq" | "x" => Command::WriteQuit,
+        "q" | "quit" => Command::Quit { force: false },
+        "q!" | "quit!" => Command::Quit { force: true },
+        "e" | "edit" => Command::Edit { path: path(), force: false },
+        "e!" | "edit!" => Command::Edit { path: path(), force: true },
+        "r" | "read" => Command::Read(path().ok_or("read needs a path")?),
+        "set" => Command::Set(path().ok_or("set needs an option")?),
+        "d" | "delete" => Command::Delete,
+        "s" | "substitute" => parse_substitute(argument)?,
+        _ => return Err(format!("unknown command {name}")),
+    };
+    Ok(Parsed { range, command })
+}
+
+fn parse_range(input: &str) -> Result<(Option<AddressRange>, &str), String> {
+    if let Some(rest) = input.strip_prefix('%') {
+        return Ok((Some((Address::Line(1), Address::Last)), rest));
+    }
+    let Some((first, rest)) = parse_address(input)? else {
+        return Ok((None, input));
+    };
+    if let Some(rest) = rest.strip_prefix(',') {
+        let (last, rest) = parse_address(rest)?.ok_or("missing end of range")?;
+        return Ok((Some((first, last)), rest));
+    }
+    Ok((Some((first, first)), rest))
+}
+
+fn parse_address(input: &str) -> Result<Option<(Address, &str)>, String> {
+    if let Some(rest) = input.strip_prefix('.') {
+        return Ok(Some((Address::Current, rest)));
+    }
+    if let Some(rest) = input.strip_prefix('$') {
+        return Ok(Some((Address::Last, rest)));
+    }
+    let digits = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    if digits == 0 {
+        return Ok(None);
+    }
+    let line = input.get(..digits).unwrap_or_default().parse().map_err(|_| "invalid line number")?;
+    Ok(Some((Address::Line(line), input.get(digits..).unwrap_or_default())))
+}
+
+// `/pattern/replacement/flags`, any char may stand in for `/` and a
+// backslash escapes it
+fn parse_substitute(argument: &str) -> Result<Command, String> {
+    let mut chars = argument.chars();
+    let delimiter = chars.next().ok_or("substitute needs /pattern/replacement/")?;
+    let mut parts = vec![String::new()];
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => parts.last_mut().map_or((), |part| part.push(next)),
+                Some(next) => parts.last_mut().map_or((), |part| {
+                    part.push('\\');
+                    part.push(next);
+                }),
+                None => (),
+            },
+            c if c == delimiter => parts.push(String::new()),
+            c => parts.last_mut().map_or((), |part| part.push(c)),
+        }
+    }
+    let mut parts = parts.into_iter();
+    let pattern = parts.next().filter(|pattern| !pattern.is_empty()).ok_or("substitute needs a pattern")?;
+    let replacement = parts.next().unwrap_or_default();
+    let flags = parts.next().unwrap_or_default();
+    Ok(Command::Substitute { pattern, replacement, global: flags.contains('g') })
+}
+
+// rows `range` covers, counting from 0, in order
+pub fn resolve(range: AddressRange, current: usize, rows: usize) -> Option<(usize, usize)> {
+    let row = |address: Address| match address {
+        Address::Line(line) => line.checked_sub(1),
+        Address::Current => Some(current),
+        Address::Last => rows.checked_sub(1),
+    };
+    let (first, last) = (row(range.0)?, row(range.1)?);
+    if first > last || last >= rows {
+        return None;
+    }
+    Some((first, last))
+}
+
+// the input with its last word completed as far as every candidate
+// agrees, command names for the first word and paths after it
+pub fn complete(input: &str) -> String {
+    let (head, word) = input.rfind(' ').map_or(("", input), |index| input.split_at(index.saturating_add(1)));
+    let candidates: Vec<String> = if head.is_empty() {
+        COMMANDS.iter().filter(|name| name.starts_with(word)).map(|name| format!("{name} ")).collect()
+    } else {
+        complete_path(word)
+    };
+    let Some(first) = candidates.first() else {
+        return input.to_string();
+    };
+    let common = candidates.iter().fold(first.clone(), |common, candidate| {
+        common.chars().zip(candidate.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+    });
+    if common.len() < word.len() {
+        return input.to_string();
+    }
+    format!("{head}{common}")
+}
+
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = word.rfind('/').map_or(("", word), |index| word.split_at(index.saturating_add(1)));
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // hidden files only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{dir}{name}{slash}"))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::{complete, parse, resolve, Address, Command};
+
+    fn range(input: &str) -> Option<(Address, Address)> {
+        parse(input).ok().and_then(|parsed| parsed.range)
+    }
+
+    // pattern, replacement and whether it is global
+    fn substitute(input: &str) -> Option<(String, String, bool)> {
+        match parse(input).ok()?.command {
+            Command::Substitute { pattern, replacement, global } => Some((pattern, replacement, global)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(range("%d"), Some((Address::Line(1), Address::Last)));
+        assert_eq!(range("3,7d"), Some((Address::Line(3), Address::Line(7))));
+        assert_eq!(range(".,$ d"), Some((Address::Current, Address::Last)));
+        assert_eq!(range("12"), Some((Address::Line(12), Address::Line(12))));
+        assert_eq!(range("d"), None);
+        assert!(parse("3,d").is_err());
+        assert!(parse("99999999999999999999999d").is_err());
+    }
+
+    #[test]
+    fn a_range_on_its_own_goes_to_it() {
+        assert!(matches!(parse("$").map(|parsed| parsed.command), Ok(Command::GoTo)));
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn commands_and_arguments() {
+        assert!(matches!(parse("w out.txt").map(|parsed| parsed.command), Ok(Command::Write(Some(path))) if path == "out.txt"));
+        assert!(matches!(parse("write").map(|parsed| parsed.command), Ok(Command::Write(None))));
+        assert!(matches!(parse("q!").map(|parsed| parsed.command), Ok(Command::Quit { force: true })));
+        assert!(matches!(parse("e! ").map(|parsed| parsed.command), Ok(Command::Edit { path: None, force: true })));
+        assert!(matches!(parse("set ft=rust").map(|parsed| parsed.command), Ok(Command::Set(option)) if option == "ft=rust"));
+        assert_eq!(parse("read").err().as_deref(), Some("read needs a path"));
+        assert_eq!(parse("frobnicate").err().as_deref(), Some("unknown command frobnicate"));
+    }
+
+    #[test]
+    fn substitute_delimiters_and_flags() {
+        assert_eq!(substitute("%s/a/b/g"), Some(("a".to_string(), "b".to_string(), true)));
+        assert_eq!(substitute("s#x#y#"), Some(("x".to_string(), "y".to_string(), false)));
+        assert_eq!(substitute("s/a/"), Some(("a".to_string(), String::new(), false)));
+        assert_eq!(substitute("substitute /old/new/g"), Some(("old".to_string(), "new".to_string(), true)));
+        assert!(parse("s//b/").is_err());
+    }
+
+    #[test]
+    fn substitute_escapes() {
+        // an escaped delimiter is taken as is, other escapes are kept for the regex
+        assert_eq!(substitute(r"s/a\/b/c\.d/"), Some(("a/b".to_string(), r"c\.d".to_string(), false)));
+        assert_eq!(substitute(r"s|\d+|\||g"), Some((r"\d+".to_string(), "|".to_string(), true)));
+    }
+
+    #[test]
+    fn resolved_rows() {
+        assert_eq!(resolve((Address::Line(1), Address::Last), 4, 10), Some((0, 9)));
+        assert_eq!(resolve((Address::Current, Address::Current), 4, 10), Some((4, 4)));
+        assert_eq!(resolve((Address::Line(5), Address::Line(2)), 0, 10), None);
+        assert_eq!(resolve((Address::Line(3), Address::Line(11)), 0, 10), None);
+        assert_eq!(resolve((Address::Line(0), Address::Line(1)), 0, 10), None);
+        assert_eq!(resolve((Address::Last, Address::Last), 0, 0), None);
+    }
+
+    #[test]
+    fn command_names_complete_as_far_as_they_agree() {
+        assert_eq!(complete("wr"), "write ");
+        assert_eq!(complete("su"), "substitute ");
+        assert_eq!(complete("s"), "s");
+        assert_eq!(complete("q"), "q");
+        assert_eq!(complete("zz"), "zz");
+    }
+
+    #[test]
+    fn paths_complete_after_the_command() {
+        let directory = std::env::temp_dir().join(format!("heitx-command-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("source")).expect("temporary directory");
+        fs::write(directory.join("sorted.txt"), "").expect("file written");
+        fs::write(directory.join(".hidden"), "").expect("file written");
+        let dir = directory.display();
+        assert_eq!(complete(&format!("e {dir}/sou")), format!("e {dir}/source/"));
+        assert_eq!(complete(&format!("e {dir}/so")), format!("e {dir}/so"));
+        assert_eq!(complete(&format!("e {dir}/h")), format!("e {dir}/h"));
+        assert_eq!(complete(&format!("e {dir}/.h")), format!("e {dir}/.hidden"));
+        fs::remove_dir_all(&directory).expect("temporary directory removed");
+    }
+}
@@ -0,0 +1,12 @@
+use std::env;
+use std::path::PathBuf;
+
+// $XDG_CONFIG_HOME/heitx, falling back to ~/.config/heitx
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        if !dir.is_empty() {
+            return Some(PathBuf::from(dir).join("heitx"));
+        }
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("heitx"))
+}
@@ -1,8 +1,14 @@
 
 use crate::{Position, Row, SearchDirection};
+use std::cmp;
+use std::ops::Range;
 use std::fs;
 use std::io::{Error, Write};
 use crate::filetype::FileType;
+use crate::highlighting::{self, State};
+use unicode_segmentation::UnicodeSegmentation;
+#[cfg(feature = "tree-sitter")]
+use crate::syntax::Syntax;
 
 #[derive(Default)]
 pub struct Document {
@@ -10,28 +16,72 @@ pub struct Document {
     pub filename: Option<String>,
     dirty: bool,
     filetype: FileType,
+    // set by hand, saving does not detect it again
+    manual_filetype: bool,
+    // rows holding the marks of `highlight_brackets`
+    bracket_rows: Vec<usize>,
+    // rows drawn with a selection
+    selected_rows: Range<usize>,
+    #[cfg(feature = "tree-sitter")]
+    syntax: Option<Syntax>,
 }
 
+// rows searched for modelines at either end of the file
+const MODELINE_ROWS: usize = 5;
+// how far to look for the partner of a bracket
+const BRACKET_SCAN_ROWS: usize = 5000;
+// how far past the visible rows the bracket at the cursor is matched
+const BRACKET_MARK_ROWS: usize = 100;
+const OPENING_BRACKETS: &str = "([{";
+const CLOSING_BRACKETS: &str = ")]}";
+
 impl Document {
     pub fn open(filename: &str) -> Result<Self, std::io::Error> {
         let contents = fs::read_to_string(filename)?;
         let mut rows = Vec::new();
-        let filetype = FileType::from(filename);
         for value in contents.lines() {
             rows.push(Row::from(value));
         }
-        Ok(Self {
+        let mut document = Self {
             rows,
             filename: Some(filename.to_string()),
-            dirty: false,
-            filetype,
-        })
+            ..Self::default()
+        };
+        document.detect_filetype();
+        Ok(document)
     }
 
     pub fn filetype(&self) -> String {
         self.filetype.name()
     }
 
+    // returns false when no language goes by `name`
+    pub fn set_filetype(&mut self, name: &str) -> bool {
+        let Some(filetype) = FileType::by_name(name) else {
+            return false;
+        };
+        self.filetype = filetype;
+        self.manual_filetype = true;
+        self.reset_highlighting();
+        true
+    }
+
+    fn detect_filetype(&mut self) {
+        let head = self.rows.iter().take(MODELINE_ROWS);
+        let tail = self.rows.iter().skip(MODELINE_ROWS).rev().take(MODELINE_ROWS);
+        let lines: Vec<&str> = head.chain(tail).map(Row::as_str).collect();
+        self.filetype = FileType::detect(self.filename.as_deref(), &lines);
+        self.reset_highlighting();
+    }
+
+    fn reset_highlighting(&mut self) {
+        self.invalidate_highlighting();
+        #[cfg(feature = "tree-sitter")]
+        {
+            self.syntax = self.filetype.grammar().and_then(Syntax::new);
+        }
+    }
+
     pub fn row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
     }
@@ -53,10 +103,12 @@ impl Document {
         if c == '\n' {
             self.insert_newline(position);
         } else if position.y == self.rows.len() {
+            self.edit_syntax(position, "", &format!("{c}\n"));
             let mut row = Row::default();
             row.insert(0, c);
             self.rows.push(row);
         } else {
+            self.edit_syntax(position, "", c.encode_utf8(&mut [0; 4]));
             #[allow(clippy::indexing_slicing)]
             let row = &mut self.rows[position.y];
             row.insert(position.x, c);
@@ -64,7 +116,7 @@ impl Document {
     }
 
     // simple delete
-    #[allow(clippy::integer_arithmetic)]
+    #[allow(clippy::arithmetic_side_effects)]
     pub fn delete(&mut self, pos: &Position) {
         if pos.y >= self.rows.len() {
             return;
@@ -78,19 +130,391 @@ impl Document {
             ry to delete from the current row.
          */
         if pos.x == self.rows.get_mut(pos.y).unwrap().len() && pos.y + 1 < self.len() {
+            self.edit_syntax(pos, "\n", "");
             let next_row = self.rows.remove(pos.y + 1);
             let row = self.rows.get_mut(pos.y).unwrap();
             row.append(&next_row);
         } else {
+            if let Some(grapheme) = self.rows[pos.y].grapheme(pos.x).map(str::to_string) {
+                self.edit_syntax(pos, &grapheme, "");
+            }
             let row = self.rows.get_mut(pos.y).unwrap();
             row.delete(pos.x);
         }
     }
 
+    fn insert_str(&mut self, at: &Position, text: &str) {
+        let mut position = at.clone();
+        for c in text.chars() {
+            self.insert(&position, c);
+            position.x = position.x.saturating_add(1);
+        }
+    }
+
+    // Enter: the new row keeps the indentation, one level deeper after an
+    // opening bracket, and `{|}` becomes three rows; returns the cursor
+    pub fn newline(&mut self, at: &Position) -> Position {
+        let Some(row) = self.rows.get(at.y) else {
+            self.insert(at, '\n');
+            return Position { x: 0, y: at.y.saturating_add(1) };
+        };
+        let indent: String = row.indentation().chars().take(at.x).collect();
+        let is_blank = |x: &usize| row.grapheme(*x).is_some_and(|g| g.trim().is_empty());
+        let before = (0..at.x).rev().find(|x| !is_blank(x)).filter(|x| row.is_code(*x));
+        let after = (at.x..row.len()).find(|x| !is_blank(x));
+        let blanks = after.unwrap_or(row.len()).saturating_sub(at.x);
+        let opens = before.and_then(|x| row.grapheme(x)).is_some_and(|g| OPENING_BRACKETS.contains(g));
+        let closes = opens && after.zip(before).is_some_and(|(after, before)| {
+            self.brackets_pair(&Position { x: before, y: at.y }, &Position { x: after, y: at.y })
+        });
+        // whitespace in front of the cursor is not carried over
+        for _ in 0..blanks {
+            self.delete(at);
+        }
+        self.insert(at, '\n');
+        let inner = if opens { format!("{indent}{}", self.filetype.indent()) } else { indent.clone() };
+        let cursor = Position { x: inner.chars().count(), y: at.y.saturating_add(1) };
+        self.insert_str(&Position { x: 0, y: cursor.y }, &inner);
+        if closes {
+            self.insert(&cursor, '\n');
+            self.insert_str(&Position { x: 0, y: cursor.y.saturating_add(1) }, &indent);
+        }
+        cursor
+    }
+
+    // types `c` at `at`: a closer right after the cursor is typed over, an
+    // opener gets its closer where pairing makes sense; returns the cursor
+    pub fn type_char(&mut self, at: &Position, c: char) -> Position {
+        let next = Position { x: at.x.saturating_add(1), y: at.y };
+        let after = self.rows.get(at.y).and_then(|row| row.grapheme(at.x)).and_then(|g| g.chars().next());
+        let pairs = self.filetype.auto_pairs();
+        if after == Some(c) && pairs.iter().any(|(_, close)| *close == c) {
+            return next;
+        }
+        if let Some((_, close)) = pairs.iter().find(|(open, _)| *open == c) {
+            if self.should_pair(at, c) {
+                self.insert(at, c);
+                self.insert(&next, *close);
+                return next;
+            }
+        }
+        self.insert(at, c);
+        self.reindent_typed(at)
+    }
+
+    fn should_pair(&self, at: &Position, open: char) -> bool {
+        let Some(row) = self.rows.get(at.y) else {
+            return true;
+        };
+        let before = at.x.checked_sub(1).and_then(|x| row.highlighting_at(x));
+        let after = row.highlighting_at(at.x);
+        let is_comment = |hl_type: highlighting::Type| matches!(hl_type,
+            highlighting::Type::Comment | highlighting::Type::MultipleComments | highlighting::Type::DocComment);
+        let in_comment = before.map_or(after.is_some_and(is_comment), |before| is_comment(before) && after.is_none_or(is_comment));
+        // only a word, a closer or punctuation may follow the new pair
+        let next_char = row.grapheme(at.x).and_then(|g| g.chars().next());
+        if in_comment || next_char.is_some_and(|c| !c.is_whitespace() && !")]},;:".contains(c)) {
+            return false;
+        }
+        if !OPENING_BRACKETS.contains(open) {
+            let in_string = before.is_some_and(highlighting::Type::is_comment_or_string)
+                && after.is_some_and(highlighting::Type::is_comment_or_string);
+            let opts = self.filetype.highlighting_options();
+            let before_cursor: Vec<char> = row.as_str().chars().take(at.x).collect();
+            // `don't` or `x'` are not strings, but `b'` and `f"` are
+            let word_len = before_cursor.iter().rev().take_while(|c| c.is_alphanumeric() || **c == '_').count();
+            let word: String = before_cursor.iter().skip(before_cursor.len().saturating_sub(word_len)).collect();
+            let is_prefix = opts.string_prefixes().iter().chain(opts.raw_strings()).chain(opts.character_prefixes()).any(|prefix| *prefix == word);
+            if in_string || (!word.is_empty() && !is_prefix) {
+                return false;
+            }
+            // `&'a` and `T: 'a` are lifetimes, a character starts an expression
+            if open == '\'' && opts.lifetimes() {
+                let last = before_cursor.iter().rev().find(|c| !c.is_whitespace());
+                return last.is_none_or(|c| "=([{".contains(*c));
+            }
+        }
+        true
+    }
+
+    // Backspace with the cursor between an empty pair removes both halves;
+    // `at` is the opener
+    pub fn delete_pair(&mut self, at: &Position) -> bool {
+        let Some(row) = self.rows.get(at.y) else {
+            return false;
+        };
+        let open = row.grapheme(at.x).and_then(|g| g.chars().next());
+        let close = row.grapheme(at.x.saturating_add(1)).and_then(|g| g.chars().next());
+        let is_pair = open.zip(close).is_some_and(|pair| self.filetype.auto_pairs().contains(&pair));
+        if is_pair {
+            self.delete(at);
+            self.delete(at);
+        }
+        is_pair
+    }
+
+    pub fn wrap(&mut self, start: &Position, end: &Position, open: char) -> bool {
+        let Some((_, close)) = self.filetype.auto_pairs().into_iter().find(|(o, _)| *o == open) else {
+            return false;
+        };
+        self.insert(end, close);
+        self.insert(start, open);
+        true
+    }
+
+    // next word stop from `at`, moving onto the neighbouring row at a row edge
+    pub fn word_boundary(&self, at: &Position, direction: SearchDirection, subwords: bool) -> Position {
+        let Some(row) = self.rows.get(at.y) else {
+            return at.clone();
+        };
+        if let Some(x) = row.word_boundary(at.x, direction, subwords) {
+            return Position { x, y: at.y };
+        }
+        match direction {
+            SearchDirection::Forward if at.y.saturating_add(1) < self.rows.len() => Position { x: 0, y: at.y.saturating_add(1) },
+            SearchDirection::Backward if at.y > 0 => {
+                let y = at.y.saturating_sub(1);
+                Position { x: self.rows.get(y).map_or(0, Row::len), y }
+            },
+            _ => at.clone(),
+        }
+    }
+
+    // deletes up to the next word stop and returns where the cursor ends up
+    pub fn delete_word(&mut self, at: &Position, direction: SearchDirection, subwords: bool) -> Position {
+        let boundary = self.word_boundary(at, direction, subwords);
+        match direction {
+            SearchDirection::Forward => {
+                self.delete_range(at, &boundary);
+                at.clone()
+            },
+            SearchDirection::Backward => {
+                self.delete_range(&boundary, at);
+                boundary
+            },
+        }
+    }
+
+    // removes the text from `start` up to, not including, `end`
+    pub fn delete_range(&mut self, start: &Position, end: &Position) {
+        let mut count = 0_usize;
+        let mut y = start.y;
+        let mut x = start.x;
+        while y < end.y {
+            count = count.saturating_add(self.rows.get(y).map_or(0, Row::len).saturating_sub(x)).saturating_add(1);
+            y = y.saturating_add(1);
+            x = 0;
+        }
+        count = count.saturating_add(end.x.saturating_sub(x));
+        for _ in 0..count {
+            self.delete(start);
+        }
+    }
+
+    pub fn highlight_selection(&mut self, selection: Option<&(Position, Position)>) {
+        for y in self.selected_rows.clone() {
+            if let Some(row) = self.rows.get_mut(y) {
+                row.set_selection(None);
+            }
+        }
+        self.selected_rows = 0..0;
+        let Some((start, end)) = selection else {
+            return;
+        };
+        for y in start.y..=end.y {
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { usize::MAX };
+            if let Some(row) = self.rows.get_mut(y) {
+                row.set_selection(Some(from..to));
+            }
+        }
+        self.selected_rows = start.y..end.y.saturating_add(1);
+    }
+
+    // replaces whole rows, every line operation goes through here
+    fn replace_rows(&mut self, rows: Range<usize>, new_rows: Vec<String>) {
+        let rows = cmp::min(rows.start, self.rows.len())..cmp::min(rows.end, self.rows.len());
+        #[allow(clippy::indexing_slicing)]
+        let removed: String = self.rows[rows.clone()].iter().map(|row| format!("{}\n", row.as_str())).collect();
+        let inserted: String = new_rows.iter().map(|row| format!("{row}\n")).collect();
+        self.edit_syntax(&Position { x: 0, y: rows.start }, &removed, &inserted);
+        self.rows.splice(rows, new_rows.iter().map(|row| Row::from(row.as_str())));
+        self.dirty = true;
+    }
+
+    fn row_strings(&self, rows: Range<usize>) -> Vec<String> {
+        self.rows.iter().skip(rows.start).take(rows.len()).map(|row| row.as_str().to_string()).collect()
+    }
+
+    pub fn replace_row(&mut self, y: usize, text: &str) {
+        if y < self.rows.len() {
+            self.replace_rows(y..y.saturating_add(1), vec![text.to_string()]);
+        }
+    }
+
+    pub fn insert_rows(&mut self, y: usize, rows: Vec<String>) {
+        self.replace_rows(y..y, rows);
+    }
+
+    pub fn duplicate_rows(&mut self, rows: Range<usize>) {
+        let copies = self.row_strings(rows.clone());
+        self.replace_rows(rows.end..rows.end, copies);
+    }
+
+    pub fn delete_rows(&mut self, rows: Range<usize>) {
+        self.replace_rows(rows, Vec::new());
+    }
+
+    // false when the rows are already at the top or bottom
+    pub fn move_rows(&mut self, rows: Range<usize>, direction: SearchDirection) -> bool {
+        let end = cmp::min(rows.end, self.rows.len());
+        if rows.start >= end {
+            return false;
+        }
+        let around = match direction {
+            SearchDirection::Backward if rows.start > 0 => rows.start.saturating_sub(1)..end,
+            SearchDirection::Forward if end < self.rows.len() => rows.start..end.saturating_add(1),
+            _ => return false,
+        };
+        let mut moved = self.row_strings(around.clone());
+        if direction == SearchDirection::Backward {
+            moved.rotate_left(1);
+        } else {
+            moved.rotate_right(1);
+        }
+        self.replace_rows(around, moved);
+        true
+    }
+
+    // appends the next row, the whitespace between them becomes one space
+    pub fn join_rows(&mut self, y: usize) {
+        if y.saturating_add(1) >= self.rows.len() {
+            return;
+        }
+        let rows = self.row_strings(y..y.saturating_add(2));
+        let (Some(first), Some(second)) = (rows.first(), rows.get(1)) else {
+            return;
+        };
+        let (first, second) = (first.trim_end(), second.trim_start());
+        let separator = if first.is_empty() || second.is_empty() { "" } else { " " };
+        self.replace_rows(y..y.saturating_add(2), vec![format!("{first}{separator}{second}")]);
+    }
+
+    pub fn insert_blank_row(&mut self, y: usize) {
+        self.replace_rows(y..y, vec![String::new()]);
+    }
+
+    pub fn sort_rows(&mut self, rows: Range<usize>, unique: bool) {
+        let mut sorted = self.row_strings(rows.clone());
+        sorted.sort();
+        if unique {
+            sorted.dedup();
+        }
+        self.replace_rows(rows, sorted);
+    }
+
+    // comments out rows `rows` with the filetype's line comment, aligned on
+    // the least indented row, or uncomments them when all are commented;
+    // false when the filetype has no line comment
+    pub fn toggle_line_comment(&mut self, rows: Range<usize>) -> bool {
+        let opts = self.filetype.highlighting_options();
+        let Some(token) = opts.line_comments().first().cloned() else {
+            return false;
+        };
+        // `/// doc` starts with `//` but is no commented out line
+        let doc_comments: Vec<String> = opts.doc_comments().iter().filter(|doc| doc.len() > token.len()).cloned().collect();
+        let rows = rows.start..cmp::min(rows.end, self.rows.len());
+        #[allow(clippy::indexing_slicing)]
+        let contents: Vec<(usize, String)> = self.rows[rows.clone()].iter()
+            .map(|row| (row.indentation().chars().count(), row.as_str().trim().to_string()))
+            .collect();
+        let is_blank = |content: &String| content.is_empty();
+        let commented = contents.iter().filter(|(_, content)| !is_blank(content)).all(|(_, content)| {
+            content.starts_with(&token) && !doc_comments.iter().any(|doc| content.starts_with(doc))
+        });
+        let column = contents.iter().filter(|(_, content)| !is_blank(content)).map(|(indent, _)| *indent).min().unwrap_or(0);
+        for (y, (indent, content)) in rows.zip(contents) {
+            if is_blank(&content) {
+                continue;
+            }
+            if commented {
+                let at = Position { x: indent, y };
+                let len = token.chars().count().saturating_add(usize::from(content[token.len()..].starts_with(' ')));
+                self.delete_range(&at, &Position { x: indent.saturating_add(len), y });
+            } else {
+                self.insert_str(&Position { x: column, y }, &format!("{token} "));
+            }
+        }
+        true
+    }
+
+    // wraps `start..end` in the filetype's block comment, or unwraps it when
+    // it already is one; false when the filetype has no block comment
+    pub fn toggle_block_comment(&mut self, start: &Position, end: &Position) -> bool {
+        let opts = self.filetype.highlighting_options();
+        let Some(comment) = opts.block_comments().iter().find(|comment| !comment.doc).or_else(|| opts.block_comments().first()) else {
+            return false;
+        };
+        let (open, close) = (comment.start.clone(), comment.end.clone());
+        let text = |y: usize| self.rows.get(y).map(|row| row.as_str().chars().collect::<Vec<char>>()).unwrap_or_default();
+        let first = text(start.y);
+        let last = text(end.y);
+        // the comment may be surrounded by whitespace inside the range
+        let first_end = if start.y == end.y { end.x } else { first.len() };
+        let last_start = if start.y == end.y { start.x } else { 0 };
+        let open_at = (start.x..first_end).find(|x| first.get(*x).is_some_and(|c| !c.is_whitespace())).unwrap_or(start.x);
+        let close_end = (last_start..end.x).rev()
+            .find(|x| last.get(*x).is_some_and(|c| !c.is_whitespace()))
+            .map_or(end.x, |x| x.saturating_add(1));
+        let starts = first.iter().skip(open_at).copied().collect::<String>().starts_with(&open);
+        let ends = last.iter().take(close_end).copied().collect::<String>().ends_with(&close);
+        let close_len = close.chars().count();
+        let open_len = open.chars().count();
+        if starts && ends && (start.y != end.y || close_end >= open_at.saturating_add(open_len).saturating_add(close_len)) {
+            let close_at = close_end.saturating_sub(close_len);
+            let padded_close = usize::from(close_at > 0 && last.get(close_at.saturating_sub(1)) == Some(&' '));
+            self.delete_range(&Position { x: close_at.saturating_sub(padded_close), y: end.y }, &Position { x: close_end, y: end.y });
+            let padded_open = usize::from(first.get(open_at.saturating_add(open_len)) == Some(&' '));
+            self.delete_range(&Position { x: open_at, y: start.y }, &Position { x: open_at.saturating_add(open_len).saturating_add(padded_open), y: start.y });
+        } else {
+            self.insert_str(&Position { x: close_end, y: end.y }, &format!(" {close}"));
+            self.insert_str(&Position { x: open_at, y: start.y }, &format!("{open} "));
+        }
+        true
+    }
+
+    // a closing bracket typed as the first non-blank character of its row
+    // lines up with the row of its opener; returns the cursor after typing at `at`
+    pub fn reindent_typed(&mut self, at: &Position) -> Position {
+        let next = Position { x: at.x.saturating_add(1), y: at.y };
+        let Some(row) = self.rows.get(at.y) else {
+            return next;
+        };
+        let indent_len = row.indentation().chars().count();
+        if indent_len != at.x || !row.grapheme(at.x).is_some_and(|g| CLOSING_BRACKETS.contains(g)) {
+            return next;
+        }
+        let current = row.indentation().to_string();
+        let target = match self.bracket_partner(at) {
+            Some(opener) => self.rows.get(opener.y).map_or_else(String::new, |row| row.indentation().to_string()),
+            None => current.strip_suffix(self.filetype.indent()).unwrap_or(&current).to_string(),
+        };
+        if target == current {
+            return next;
+        }
+        let start = Position { x: 0, y: at.y };
+        for _ in 0..indent_len {
+            self.delete(&start);
+        }
+        self.insert_str(&start, &target);
+        Position { x: target.chars().count().saturating_add(1), y: at.y }
+    }
+
     fn insert_newline(&mut self, pos: &Position) {
         if pos.y > self.rows.len() {
             return;
         }
+        self.edit_syntax(pos, "", "\n");
         if pos.y == self.rows.len() {
             self.rows.push(Row::default());
             return;
@@ -98,14 +522,28 @@ impl Document {
         #[allow(clippy::indexing_slicing)]
         let current_row = &mut self.rows[pos.y];
         let new_row = current_row.split(pos.x);
-        #[allow(clippy::integer_arithmetic)]
+        #[allow(clippy::arithmetic_side_effects)]
         self.rows.insert(pos.y + 1, new_row);
     }
 
+    // tells the syntax tree about an edit before the rows change
+    #[cfg(feature = "tree-sitter")]
+    fn edit_syntax(&mut self, at: &Position, removed: &str, inserted: &str) {
+        if let Some(syntax) = &mut self.syntax {
+            syntax.edit(&self.rows, at, removed, inserted);
+        }
+    }
+
+    #[cfg(not(feature = "tree-sitter"))]
+    #[allow(clippy::unused_self)]
+    fn edit_syntax(&mut self, _at: &Position, _removed: &str, _inserted: &str) {}
+
     pub fn save_to_disk(&mut self) -> Result<(), Error> {
         if let Some(filename) = &self.filename {
             let mut file = fs::File::create(filename)?;
-            self.filetype = FileType::from(filename);
+            if !self.manual_filetype {
+                self.detect_filetype();
+            }
             for row in &mut self.rows {
                 file.write_all(row.as_bytes())?;
                 file.write_all(b"\n")?;
@@ -115,6 +553,16 @@ impl Document {
         Ok(())
     }
 
+    // the rows as they would be saved
+    pub fn text(&self) -> String {
+        self.rows.iter().flat_map(|row| [row.as_str(), "\n"]).collect()
+    }
+
+    // for contents that no longer match any file
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
@@ -136,7 +584,7 @@ impl Document {
         };
         for _ in start..end {
             if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.search(&query, position.x, direction) {
+                if let Some(x) = row.search(query, position.x, direction) {
                     position.x = x;
                     return Some(position);
                 }
@@ -154,20 +602,234 @@ impl Document {
         None
     }
 
-    pub fn highlight(&mut self, word: &Option<String>, until: Option<usize>) {
-        let mut start_with_comment = false;
-        let until = if let Some(until) = until {
-            if until.saturating_add(1) < self.rows.len() {
-                until.saturating_add(1)
-            } else {
-                self.rows.len()
+    // highlights rows `start..end`, rows above are only lexed as far as
+    // needed to know the state the window starts in
+    pub fn highlight(&mut self, word: &Option<String>, start: usize, end: usize) {
+        let end = cmp::min(end, self.rows.len());
+        let start = cmp::min(start, end);
+        self.highlight_rows(start, end);
+        #[allow(clippy::indexing_slicing)]
+        for row in &mut self.rows[start..end] {
+            row.highlight_match(word);
+        }
+    }
+
+    fn highlight_rows(&mut self, start: usize, end: usize) {
+        if !self.highlight_syntax(start, end) {
+            let mut state = State::Normal;
+            #[allow(clippy::indexing_slicing)]
+            for row in &mut self.rows[..end] {
+                // rows whose cached entry state still matches are skipped
+                state = row.highlight(self.filetype.highlighting_options(), state);
             }
+        }
+    }
+
+    // the bracket under the cursor, or else the one just before it, or
+    // else the opening one of the pair around the cursor; brackets in
+    // strings and comments do not count
+    pub fn find_bracket(&mut self, at: &Position) -> Option<Position> {
+        let rows = at.y.saturating_sub(BRACKET_SCAN_ROWS)..at.y.saturating_add(BRACKET_SCAN_ROWS);
+        self.bracket_near(at, rows)
+    }
+
+    // `find_bracket` looking for the pair around `at` only within `rows`
+    fn bracket_near(&mut self, at: &Position, rows: Range<usize>) -> Option<Position> {
+        self.highlight_rows(at.y, at.y.saturating_add(1));
+        let row = self.rows.get(at.y)?;
+        let is_bracket = |x: usize| row.grapheme(x).is_some_and(|g| OPENING_BRACKETS.contains(g) || CLOSING_BRACKETS.contains(g)) && row.is_code(x);
+        let adjacent = [Some(at.x), at.x.checked_sub(1)].into_iter()
+            .flatten()
+            .find(|x| is_bracket(*x))
+            .map(|x| Position { x, y: at.y });
+        if adjacent.is_some() {
+            return adjacent;
+        }
+        self.scan(at, false, rows).ok().flatten()
+    }
+
+    // the bracket closing or opening the one at `bracket`, of any kind;
+    // compare them with `brackets_pair` to tell a match from a mismatch
+    pub fn bracket_partner(&mut self, bracket: &Position) -> Option<Position> {
+        let rows = bracket.y.saturating_sub(BRACKET_SCAN_ROWS)..bracket.y.saturating_add(BRACKET_SCAN_ROWS);
+        self.scan_partner(bracket, rows).ok().flatten()
+    }
+
+    // the partner within `rows`, None when there is none in the document
+    // and an error when the scan left `rows` before finding it
+    fn scan_partner(&mut self, bracket: &Position, rows: Range<usize>) -> Result<Option<Position>, ()> {
+        let Some(forward) = self.rows.get(bracket.y).and_then(|row| row.grapheme(bracket.x)).map(|g| OPENING_BRACKETS.contains(g)) else {
+            return Ok(None);
+        };
+        self.scan(bracket, forward, rows)
+    }
+
+    // the first bracket after `from`, or before it when not `forward`,
+    // that is left unpaired by the brackets in between
+    fn scan(&mut self, from: &Position, forward: bool, rows: Range<usize>) -> Result<Option<Position>, ()> {
+        let (start, end) = if forward {
+            (from.y, cmp::min(self.rows.len(), rows.end))
         } else {
-            self.rows.len()
+            (cmp::min(rows.start, from.y), from.y.saturating_add(1))
         };
-        #[allow(clippy::indexing_slicing)]
-        for row in &mut self.rows[..until] {
-            start_with_comment = row.highlight(&self.filetype.highlighting_options(), word, start_with_comment);
+        self.highlight_rows(start, end);
+        let mut depth = 0_usize;
+        let (deeper, shallower) = if forward { (OPENING_BRACKETS, CLOSING_BRACKETS) } else { (CLOSING_BRACKETS, OPENING_BRACKETS) };
+        let ys: Box<dyn Iterator<Item = usize>> = if forward { Box::new(start..end) } else { Box::new((start..end).rev()) };
+        for y in ys {
+            let Some(row) = self.rows.get(y) else {
+                break;
+            };
+            let graphemes: Vec<&str> = row.as_str().graphemes(true).collect();
+            let columns: Box<dyn Iterator<Item = usize>> = match (forward, y == from.y) {
+                (true, true) => Box::new(from.x.saturating_add(1)..graphemes.len()),
+                (true, false) => Box::new(0..graphemes.len()),
+                (false, true) => Box::new((0..from.x).rev()),
+                (false, false) => Box::new((0..graphemes.len()).rev()),
+            };
+            for x in columns {
+                let Some(grapheme) = graphemes.get(x).filter(|_| row.is_code(x)) else {
+                    continue;
+                };
+                if deeper.contains(grapheme) {
+                    depth = depth.saturating_add(1);
+                } else if shallower.contains(grapheme) {
+                    if depth == 0 {
+                        return Ok(Some(Position { x, y }));
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+            }
+        }
+        let whole_document = if forward { end >= self.rows.len() } else { start == 0 };
+        if whole_document { Ok(None) } else { Err(()) }
+    }
+
+    pub fn brackets_pair(&self, a: &Position, b: &Position) -> bool {
+        let grapheme = |at: &Position| self.rows.get(at.y).and_then(|row| row.grapheme(at.x));
+        let kind = |at: &Position| grapheme(at).and_then(|g| OPENING_BRACKETS.find(g).or_else(|| CLOSING_BRACKETS.find(g)));
+        kind(a).is_some() && kind(a) == kind(b)
+    }
+
+    // marks the bracket at the cursor and its partner, or flags a bracket
+    // without a partner of the same kind; the partner is only looked for
+    // a little past `visible`, brackets whose partner is further away are
+    // left unmarked
+    pub fn highlight_brackets(&mut self, at: &Position, visible: Range<usize>) {
+        for y in self.bracket_rows.drain(..) {
+            if let Some(row) = self.rows.get_mut(y) {
+                row.clear_brackets();
+            }
+        }
+        let rows = visible.start.saturating_sub(BRACKET_MARK_ROWS)..visible.end.saturating_add(BRACKET_MARK_ROWS);
+        let Some(bracket) = self.bracket_near(at, rows.clone()) else {
+            return;
+        };
+        let Ok(partner) = self.scan_partner(&bracket, rows) else {
+            return;
+        };
+        let hl_type = if partner.as_ref().is_some_and(|partner| self.brackets_pair(&bracket, partner)) {
+            highlighting::Type::MatchingBracket
+        } else {
+            highlighting::Type::MismatchedBracket
+        };
+        for position in [Some(bracket), partner].into_iter().flatten() {
+            if let Some(row) = self.rows.get_mut(position.y) {
+                row.mark_bracket(position.x, hl_type);
+                self.bracket_rows.push(position.y);
+            }
+        }
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    fn highlight_syntax(&mut self, start: usize, end: usize) -> bool {
+        if let Some(syntax) = &mut self.syntax {
+            syntax.highlight(&mut self.rows, start, end);
+            return true;
         }
+        false
     }
-}
\ No newline at end of file
+
+    #[cfg(not(feature = "tree-sitter"))]
+    #[allow(clippy::unused_self)]
+    fn highlight_syntax(&mut self, _start: usize, _end: usize) -> bool {
+        false
+    }
+
+    fn invalidate_highlighting(&mut self) {
+        for row in &mut self.rows {
+            row.invalidate_highlighting();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Document;
+    use crate::Position;
+
+    fn document(language: &str, lines: &[&str]) -> Document {
+        let mut document = Document::default();
+        document.set_filetype(language);
+        document.insert_rows(0, lines.iter().map(|line| (*line).to_string()).collect());
+        document
+    }
+
+    fn lines(document: &Document) -> Vec<String> {
+        (0..document.len()).filter_map(|y| document.row(y)).map(|row| row.as_str().to_string()).collect()
+    }
+
+    #[test]
+    fn line_comment_toggles_on_and_off() {
+        let mut document = document("Rust", &["fn f() {", "    x();", "}"]);
+        document.toggle_line_comment(0..3);
+        assert_eq!(lines(&document), ["// fn f() {", "//     x();", "// }"]);
+        document.toggle_line_comment(0..3);
+        assert_eq!(lines(&document), ["fn f() {", "    x();", "}"]);
+    }
+
+    #[test]
+    fn doc_comments_are_commented_out_not_uncommented() {
+        let mut document = document("Rust", &["/// doc", "//! inner doc"]);
+        document.toggle_line_comment(0..2);
+        assert_eq!(lines(&document), ["// /// doc", "// //! inner doc"]);
+        document.toggle_line_comment(0..2);
+        assert_eq!(lines(&document), ["/// doc", "//! inner doc"]);
+    }
+
+    #[test]
+    fn bracket_partner_skips_strings_and_comments() {
+        let mut document = document("Rust", &["fn f() {", "    let s = \"}\"; // }", "}"]);
+        let partner = document.bracket_partner(&Position { x: 7, y: 0 }).map(|at| (at.x, at.y));
+        assert_eq!(partner, Some((0, 2)));
+        let partner = document.bracket_partner(&Position { x: 0, y: 2 }).map(|at| (at.x, at.y));
+        assert_eq!(partner, Some((7, 0)));
+    }
+
+    #[test]
+    fn bracket_around_the_cursor_is_found_when_none_is_next_to_it() {
+        let mut document = document("Rust", &["foo(a, \")\", b)", "{", "    x[1] // (", "    y", "}"]);
+        let bracket = |document: &mut Document, x, y| document.find_bracket(&Position { x, y }).map(|at| (at.x, at.y));
+        // on `a` and on `b`, past a bracket in a string
+        assert_eq!(bracket(&mut document, 4, 0), Some((3, 0)));
+        assert_eq!(bracket(&mut document, 12, 0), Some((3, 0)));
+        // past a closed pair and a bracket in a comment, across rows
+        assert_eq!(bracket(&mut document, 4, 3), Some((0, 1)));
+        assert_eq!(document.bracket_partner(&Position { x: 0, y: 1 }).map(|at| (at.x, at.y)), Some((0, 4)));
+        // nothing around the first word
+        assert_eq!(bracket(&mut document, 1, 0), None);
+    }
+
+    #[test]
+    fn bracket_scan_stops_at_the_rows_it_is_given() {
+        let mut lines = vec!["{"];
+        lines.extend(["x"; 50]);
+        lines.push("}");
+        let mut document = document("Rust", &lines);
+        let bracket = Position { x: 0, y: 0 };
+        assert!(document.scan_partner(&bracket, 0..10).is_err());
+        assert_eq!(document.scan_partner(&bracket, 0..60).map(|partner| partner.map(|at| at.y)), Ok(Some(51)));
+        document.insert_rows(0, vec!["(".to_string()]);
+        assert!(matches!(document.scan_partner(&Position { x: 0, y: 0 }, 0..100), Ok(None)));
+    }
+}
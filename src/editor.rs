@@ -1,12 +1,35 @@
+use std::cmp::{self, Ordering};
 use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::mem;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
 use std::time::{Duration, Instant};
 use crate::{Document, Row, Terminal};
+use crate::action::{self, Action, Palette};
+use crate::command::{self, Command};
+use crate::explorer::Explorer;
+use crate::finder::Finder;
+use crate::grep::{self, Grep};
+use crate::replace::{self, Replace};
+use crate::highlighting;
+use crate::language;
+use crate::window::{self, Direction, Layout, Rect, Split, View};
+use regex::Regex;
 use termion::event::Key;
-use termion::color;
+use unicode_segmentation::UnicodeSegmentation;
+use termion::{color, style};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const STATUS_FG_COLOR: color::LightBlack = color::LightBlack;
 const STATUS_BG_COLOR: color::LightCyan = color::LightCyan;
+const INACTIVE_STATUS_BG_COLOR: color::LightWhite = color::LightWhite;
+const RESIZE_STEP: i16 = 5;
+const EXPLORER_WIDTH: usize = 30;
+// how long a project search may run before its results are first shown
+const GREP_WAIT: Duration = Duration::from_secs(2);
+const GREP_REDRAW: Duration = Duration::from_millis(100);
 const QUIT_COUNT: u8 = 2;
 
 pub struct Editor {
@@ -18,6 +41,52 @@ pub struct Editor {
     status_message: StatusMessage,
     quit_count: u8,
     highlighted_word: Option<String>,
+    // the other end of the selection, the cursor being one end
+    selection_anchor: Option<Position>,
+    // word motions also stop inside camelCase and snake_case words
+    subword_motion: bool,
+    // column vertical movement returns to on rows long enough for it
+    desired_column: Option<usize>,
+    // every open buffer, the active one lives in the fields above and its
+    // slot here stays empty until another buffer is activated
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    // every window, the focused one's view lives in the fields above the
+    // same way the active buffer does
+    windows: Vec<View>,
+    current_window: usize,
+    layout: Layout,
+    // the file tree sidebar, kept while hidden so it reopens as it was
+    explorer: Option<Explorer>,
+    sidebar: Sidebar,
+    // the fuzzy file picker, drawn over the windows while it is open
+    finder: Option<Finder>,
+    // file shown next to the picker's list, kept until another is selected
+    preview: Option<Document>,
+    // project search results, drawn over the windows while they are open
+    grep: Option<Grep>,
+    // project replace preview, drawn over the windows while it is open
+    replace: Option<Replace>,
+    // command lines run so far, oldest first
+    command_history: Vec<String>,
+    // the action list, drawn over the windows while it is open
+    palette: Option<Palette>,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+enum Sidebar {
+    Hidden,
+    Shown,
+    Focused,
+}
+
+// a document together with where the user left off in it
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+    selection_anchor: Option<Position>,
 }
 
 #[derive(PartialEq, Copy, Clone)]
@@ -63,18 +132,25 @@ impl Editor {
 
     pub fn default() -> Self {
         let args: Vec<String> = env::args().collect();
-        let mut initial_status = String::from("HELP: Ctrl-c = quit | Ctrl-s = save | Ctrl-f = search");
-        let document = if let Some(filename) = args.get(1) {
-            let doc = Document::open(filename);
-            if let Ok(doc) = doc{
-                doc
+        let mut initial_status = String::from("HELP: Ctrl-c = quit | Ctrl-s = save | Ctrl-f = search | Ctrl-o = open | Ctrl-p = find file | Alt-x = all actions");
+        let mut buffers = Vec::new();
+        let mut explorer = None;
+        for filename in args.iter().skip(1) {
+            if Path::new(filename).is_dir() {
+                explorer = Some(Explorer::open(Path::new(filename)));
+            } else if let Ok(document) = Document::open(filename) {
+                buffers.push(Buffer { document, ..Buffer::default() });
             } else {
-                initial_status = format!("ERR: could not open file {}", filename);
-                Document::default()
+                initial_status = format!("ERR: could not open file {filename}");
             }
-        } else {
-            Document::default()
-        };
+        }
+        if buffers.is_empty() {
+            buffers.push(Buffer::default());
+        }
+        let document = buffers.first_mut().map(|buffer| mem::take(&mut buffer.document)).unwrap_or_default();
+        if let Some(error) = language::languages().errors().first() {
+            initial_status = format!("ERR: could not load language {error}");
+        }
         Self {
             should_quit: false,
             terminal: Terminal::default().expect("failed to initialize heitx terminal"),
@@ -84,34 +160,73 @@ impl Editor {
             status_message: StatusMessage::from(initial_status),
             quit_count: QUIT_COUNT,
             highlighted_word: None,
+            selection_anchor: None,
+            subword_motion: false,
+            desired_column: None,
+            buffers,
+            current_buffer: 0,
+            windows: vec![View::default()],
+            current_window: 0,
+            layout: Layout::Window(0),
+            sidebar: if explorer.is_some() { Sidebar::Focused } else { Sidebar::Hidden },
+            explorer,
+            finder: None,
+            preview: None,
+            grep: None,
+            replace: None,
+            command_history: Vec::new(),
+            palette: None,
         }
     }
 
     fn process_key(&mut self) -> Result<(), std::io::Error> {
         let press = Terminal::read_key()?;
+        // only vertical movement keeps the column it started from
+        if !matches!(press, Key::Up | Key::Down | Key::PageUp | Key::PageDown | Key::ShiftUp | Key::ShiftDown) {
+            self.desired_column = None;
+        }
+        let quit_count = self.quit_count;
+        let action = action::for_key(press);
+        if self.sidebar == Sidebar::Focused && !matches!(action, Some(Action::ToggleExplorer | Action::Quit | Action::CommandPalette)) {
+            self.explorer_key(press);
+        } else if let Some(action) = action {
+            self.run_action(action);
+        } else {
+            self.edit_key(press);
+        }
+        self.scroll();
+        // any key but another quit takes the unsaved changes warning back
+        if self.quit_count < QUIT_COUNT && self.quit_count == quit_count {
+            self.quit_count = QUIT_COUNT;
+            self.status_message = StatusMessage::from(String::new());
+        }
+        Ok(())
+    }
+
+    fn edit_key(&mut self, press: Key) {
         match press {
-            Key::Ctrl('c') => {
-                if self.quit_count > 0 && self.document.is_dirty() {
-                    self.status_message = StatusMessage::from(
-                    format!("WARING! file has unsaved changes, press Ctrl-s {} more count to quit.", self.quit_count));
-                    self.quit_count -= 1;
-                    return Ok(());
-                }
-                self.should_quit = true
-            },
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Key::Right);
-            },
+            Key::Delete | Key::Backspace if self.selection().is_some() => self.delete_selection(),
+            Key::Char(c) if self.selection().is_some() => self.type_over_selection(c),
+            Key::Char(c) => self.process_char(c),
             Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
+            Key::Backspace if self.cursor_position.x > 0 || self.cursor_position.y > 0 => {
+                self.move_cursor(Key::Left);
+                if !self.document.delete_pair(&self.cursor_position) {
                     self.document.delete(&self.cursor_position);
                 }
-            }
+            },
+            Key::Esc => self.selection_anchor = None,
+            Key::ShiftUp | Key::ShiftDown | Key::ShiftLeft | Key::ShiftRight => {
+                if self.selection_anchor.is_none() {
+                    self.selection_anchor = Some(self.cursor_position.clone());
+                }
+                self.move_cursor(match press {
+                    Key::ShiftUp => Key::Up,
+                    Key::ShiftDown => Key::Down,
+                    Key::ShiftLeft => Key::Left,
+                    _ => Key::Right,
+                });
+            },
             Key::Up
             | Key::Down
             | Key::Left
@@ -119,15 +234,78 @@ impl Editor {
             | Key::PageDown
             | Key::PageUp
             | Key::End
-            | Key::Home => self.move_cursor(press),
+            | Key::Home => {
+                self.selection_anchor = None;
+                self.move_cursor(press);
+            },
             _ => (),
         }
-        self.scroll();
-        if self.quit_count < QUIT_COUNT {
-            self.quit_count = QUIT_COUNT;
-            self.status_message = StatusMessage::from(String::new());
+    }
+
+    fn run_action(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.quit_key(),
+            Action::Save => self.save(),
+            Action::Reload => {
+                if let Err(error) = self.reload(false) {
+                    self.status_message = StatusMessage::from(format!("ERR: {error}"));
+                }
+            },
+            Action::FindFile => self.find_file(),
+            Action::ToggleExplorer => self.toggle_explorer(),
+            Action::Search => self.search(),
+            Action::SearchProject => self.search_project(),
+            Action::ReplaceInProject => self.replace_in_project(),
+            Action::GoTo => self.go_to(),
+            Action::JumpToBracket => self.jump_to_bracket(),
+            Action::CommandLine => self.command_line(),
+            // the pick runs as if its key was pressed
+            Action::CommandPalette => {
+                if let Some(action) = self.command_palette() {
+                    self.run_action(action);
+                }
+            },
+            Action::SetFiletype => self.set_filetype(),
+            Action::ToggleLineComment => self.toggle_comment(false),
+            Action::ToggleBlockComment => self.toggle_comment(true),
+            Action::WordLeft | Action::WordRight => {
+                self.selection_anchor = None;
+                let direction = if action == Action::WordLeft { SearchDirection::Backward } else { SearchDirection::Forward };
+                self.cursor_position = self.document.word_boundary(&self.cursor_position, direction, self.subword_motion);
+            },
+            Action::DeleteWordBackward | Action::DeleteWordForward if self.selection().is_some() => self.delete_selection(),
+            Action::DeleteWordBackward | Action::DeleteWordForward => {
+                let direction = if action == Action::DeleteWordForward { SearchDirection::Forward } else { SearchDirection::Backward };
+                self.cursor_position = self.document.delete_word(&self.cursor_position, direction, self.subword_motion);
+            },
+            Action::ToggleSubwordMotion => {
+                self.subword_motion = !self.subword_motion;
+                let state = if self.subword_motion { "on" } else { "off" };
+                self.status_message = StatusMessage::from(format!("sub-word motion {state}"));
+            },
+            Action::Open | Action::CloseBuffer | Action::SwitchBuffer | Action::NextBuffer | Action::PreviousBuffer => self.buffer_operation(action),
+            Action::SplitVertical
+            | Action::SplitHorizontal
+            | Action::CloseWindow
+            | Action::FocusLeft
+            | Action::FocusDown
+            | Action::FocusUp
+            | Action::FocusRight
+            | Action::GrowWindow
+            | Action::ShrinkWindow => self.window_operation(action),
+            _ => self.line_operation(action),
         }
-        Ok(())
+    }
+
+    fn quit_key(&mut self) {
+        let dirty = self.dirty_buffers();
+        if self.quit_count > 0 && !dirty.is_empty() {
+            self.status_message = StatusMessage::from(
+            format!("WARING! unsaved changes in {}, press Ctrl-c {} more count to quit.", dirty.join(", "), self.quit_count));
+            self.quit_count -= 1;
+            return;
+        }
+        self.should_quit = true;
     }
 
     fn save(&mut self) {
@@ -146,31 +324,884 @@ impl Editor {
         }
     }
 
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error> where C: FnMut(&mut Self, Key, &String), {
+    fn buffer_operation(&mut self, action: Action) {
+        let count = self.buffers.len();
+        match action {
+            Action::Open => self.open(),
+            Action::CloseBuffer => self.close_buffer(),
+            Action::SwitchBuffer => self.switch_buffer_prompt(),
+            Action::NextBuffer => self.switch_buffer(self.current_buffer.saturating_add(1) % count),
+            Action::PreviousBuffer => self.switch_buffer(self.current_buffer.checked_sub(1).unwrap_or(count.saturating_sub(1))),
+            _ => (),
+        }
+    }
+
+    fn open(&mut self) {
+        if let Some(filename) = self.prompt("open: ", |_, _, _| {}).unwrap_or(None) {
+            self.open_path(filename);
+        }
+    }
+
+    fn open_path(&mut self, filename: String) {
+        if let Some(index) = self.find_buffer(&filename) {
+            self.switch_buffer(index);
+            return;
+        }
+        let document = match Document::open(&filename) {
+            Ok(document) => document,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                self.status_message = StatusMessage::from(format!("new file {filename}"));
+                let mut document = Document::default();
+                document.filename = Some(filename);
+                document
+            },
+            Err(_) => {
+                self.status_message = StatusMessage::from(format!("ERR: could not open file {filename}"));
+                return;
+            },
+        };
+        self.buffers.push(Buffer { document, ..Buffer::default() });
+        self.switch_buffer(self.buffers.len().saturating_sub(1));
+    }
+
+    fn find_file(&mut self) {
+        self.finder = Some(Finder::start(Path::new(".")));
+        self.prompt("find file: ", |editor, key, query| {
+            let Some(finder) = &mut editor.finder else {
+                return;
+            };
+            match key {
+                Key::Up | Key::Down => finder.move_selection(key == Key::Up),
+                // the picker closing tells enter from escape
+                Key::Esc => editor.finder = None,
+                _ => finder.set_query(query),
+            }
+        }).unwrap_or(None);
+        let path = self.finder.take().and_then(|finder| finder.selected_match().map(|found| found.path.clone()));
+        self.preview = None;
+        if let Some(path) = path {
+            self.open_path(path);
+        }
+    }
+
+    fn search_project(&mut self) {
+        let Some(query) = self.prompt("search project (/regex/ for a regex): ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let pattern = match grep::pattern(&query) {
+            Ok(pattern) => pattern,
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("ERR: invalid regex: {error}"));
+                return;
+            },
+        };
+        self.grep = Some(Grep::start(Path::new("."), pattern, self.open_files()));
+        // small projects are searched before the list shows up, big ones
+        // keep filling it in between key presses
+        let start = Instant::now();
+        while let Some(grep) = self.grep.as_mut().filter(|grep| grep.is_searching() && start.elapsed() < GREP_WAIT) {
+            grep.wait(GREP_REDRAW);
+            if self.refresh_screen().is_err() {
+                break;
+            }
+        }
+        self.status_message = StatusMessage::from(format!("{query}: Up/Down to choose, Enter to open, Esc to close"));
+        let mut opened = None;
+        while opened.is_none() {
+            if self.refresh_screen().is_err() {
+                break;
+            }
+            // a search still running redraws its new hits every so often
+            let searching = self.grep.as_ref().is_some_and(Grep::is_searching);
+            let key = if searching { Terminal::read_key_timeout(GREP_REDRAW) } else { Terminal::read_key().map(Some) };
+            let (Ok(key), Some(grep)) = (key, self.grep.as_mut()) else {
+                break;
+            };
+            grep.poll();
+            let Some(key) = key else {
+                continue;
+            };
+            let page = self.terminal.size().height as usize;
+            match key {
+                Key::Up | Key::Down => grep.move_selection(key == Key::Up, 1),
+                Key::PageUp | Key::PageDown => grep.move_selection(key == Key::PageUp, page),
+                Key::Char('\n') => opened = Some(grep.selected_hit().map(|hit| (hit.path.clone(), hit.position.clone()))),
+                Key::Esc => opened = Some(None),
+                _ => (),
+            }
+        }
+        let count = self.grep.take().map_or(0, |grep| grep.hits().len());
+        self.status_message = StatusMessage::from(format!("{count} matches for {query}"));
+        if let Some(Some((path, position))) = opened {
+            self.open_path(path);
+            self.selection_anchor = None;
+            self.cursor_position = position;
+            self.move_cursor(Key::Null);
+            self.scroll();
+        }
+    }
+
+    fn replace_in_project(&mut self) {
+        let Some(query) = self.prompt("replace in project (/regex/ for a regex): ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let pattern = match grep::pattern(&query) {
+            Ok(pattern) => pattern,
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("ERR: invalid regex: {error}"));
+                return;
+            },
+        };
+        // an empty replacement is fine, only escape cancels
+        let mut cancelled = false;
+        let replacement = self.prompt(&format!("replace {query} with: "), |_, key, _| cancelled = key == Key::Esc)
+            .unwrap_or(None)
+            .unwrap_or_default();
+        if cancelled {
+            return;
+        }
+        // every match has to be known before any is replaced
+        self.grep = Some(Grep::start(Path::new("."), pattern.clone(), self.open_files()));
+        while let Some(grep) = self.grep.as_mut().filter(|grep| grep.is_searching()) {
+            grep.wait(GREP_REDRAW);
+            if self.refresh_screen().is_err() {
+                break;
+            }
+        }
+        let hits = self.grep.take().map(Grep::into_hits).unwrap_or_default();
+        if hits.is_empty() {
+            self.status_message = StatusMessage::from(format!("no matches for {query}"));
+            return;
+        }
+        self.replace = Some(Replace::new(&pattern, &replacement, grep::is_regex(&query), hits));
+        self.status_message = StatusMessage::from("Space = toggle match | f = toggle file | Enter = replace | Esc = cancel".to_string());
+        let mut apply = false;
+        loop {
+            if self.refresh_screen().is_err() {
+                break;
+            }
+            let (Ok(key), Some(replace)) = (Terminal::read_key(), self.replace.as_mut()) else {
+                break;
+            };
+            let page = self.terminal.size().height as usize / 2;
+            match key {
+                Key::Up | Key::Down => replace.move_selection(key == Key::Up, 1),
+                Key::PageUp | Key::PageDown => replace.move_selection(key == Key::PageUp, page),
+                Key::Char(' ') => replace.toggle(),
+                Key::Char('f') => replace.toggle_file(),
+                Key::Char('\n') => {
+                    apply = true;
+                    break;
+                },
+                Key::Esc => break,
+                _ => (),
+            }
+        }
+        let Some(replace) = self.replace.take() else {
+            return;
+        };
+        self.status_message = if apply {
+            StatusMessage::from(self.apply_replace(&replace))
+        } else {
+            StatusMessage::from("replace cancelled.".to_string())
+        };
+    }
+
+    // makes the included changes in open buffers and, for every other
+    // file, on disk; nothing changes when a file changed since the search
+    fn apply_replace(&mut self, replace: &Replace) -> String {
+        let mut buffers = Vec::new();
+        let mut files = Vec::new();
+        let mut count = 0_usize;
+        for (path, changes) in replace.files() {
+            count = count.saturating_add(changes.len());
+            if let Some(buffer) = self.find_buffer(path) {
+                // the hits came from the buffer's text, so it is checked and not the file
+                if replace::rewrite(&self.document(buffer).text(), &changes).is_none() {
+                    return format!("ERR: {path} changed since the search, nothing replaced");
+                }
+                buffers.push((buffer, changes));
+            } else {
+                let rewritten = fs::read_to_string(path).ok().and_then(|contents| replace::rewrite(&contents, &changes));
+                let Some(rewritten) = rewritten else {
+                    return format!("ERR: {path} changed since the search, nothing replaced");
+                };
+                files.push((path.to_string(), rewritten));
+            }
+        }
+        if let Err(error) = replace::write_all(&files) {
+            return format!("ERR: could not write the changes, nothing replaced: {error}");
+        }
+        let buffer_count = buffers.len();
+        for (buffer, changes) in buffers {
+            let document = self.document_mut(buffer);
+            let mut rows: Vec<usize> = changes.iter().map(|change| change.hit.position.y).collect();
+            rows.dedup();
+            for y in rows {
+                let on_row: Vec<_> = changes.iter().copied().filter(|change| change.hit.position.y == y).collect();
+                if let Some(line) = document.row(y).map(|row| replace::apply(row.as_str(), &on_row)) {
+                    document.replace_row(y, &line);
+                }
+            }
+        }
+        self.move_cursor(Key::Null);
+        format!("replaced {count} matches in {} files ({} written, {buffer_count} open buffers to save)", files.len().saturating_add(buffer_count), files.len())
+    }
+
+    // the action picked, None when the palette was closed
+    fn command_palette(&mut self) -> Option<Action> {
+        self.palette = Some(Palette::new());
+        self.prompt("action: ", |editor, key, query| {
+            let Some(palette) = &mut editor.palette else {
+                return;
+            };
+            match key {
+                Key::Up | Key::Down => palette.move_selection(key == Key::Up),
+                // the palette closing tells enter from escape
+                Key::Esc => editor.palette = None,
+                _ => palette.set_query(query),
+            }
+        }).unwrap_or(None);
+        self.palette.take().and_then(|palette| palette.selected_action())
+    }
+
+    // Up and Down walk the history, Tab completes commands and paths
+    fn command_line(&mut self) {
+        let mut history_index = self.command_history.len();
+        let input = self.prompt(":", |editor, key, input| match key {
+            Key::Up | Key::Down => {
+                history_index = if key == Key::Up {
+                    history_index.saturating_sub(1)
+                } else {
+                    cmp::min(history_index.saturating_add(1), editor.command_history.len())
+                };
+                *input = editor.command_history.get(history_index).cloned().unwrap_or_default();
+            },
+            Key::Char('\t') => *input = command::complete(input),
+            _ => (),
+        }).unwrap_or(None);
+        let Some(input) = input else {
+            return;
+        };
+        if self.command_history.last() != Some(&input) {
+            self.command_history.push(input.clone());
+        }
+        if let Err(error) = self.run_command(&input) {
+            self.status_message = StatusMessage::from(format!("ERR: {error}"));
+        }
+    }
+
+    fn run_command(&mut self, input: &str) -> Result<(), String> {
+        let parsed = command::parse(input)?;
+        let rows = match parsed.range {
+            Some(range) => Some(command::resolve(range, self.cursor_position.y, self.document.len()).ok_or("invalid range")?),
+            None => None,
+        };
+        let (first, last) = rows.unwrap_or((self.cursor_position.y, self.cursor_position.y));
+        match parsed.command {
+            Command::Write(path) => {
+                if path.is_some() {
+                    self.document.filename = path;
+                }
+                self.save();
+            },
+            Command::WriteQuit => {
+                self.save();
+                if !self.document.is_dirty() {
+                    self.quit(false)?;
+                }
+            },
+            Command::Quit { force } => self.quit(force)?,
+            Command::Edit { path: Some(path), .. } => self.open_path(path),
+            Command::Edit { path: None, force } => self.reload(force)?,
+            Command::Read(path) => {
+                let contents = fs::read_to_string(&path).map_err(|error| format!("could not read {path}: {error}"))?;
+                let y = cmp::min(last.saturating_add(1), self.document.len());
+                self.document.insert_rows(y, contents.lines().map(str::to_string).collect());
+            },
+            Command::Set(option) => self.set_option(&option)?,
+            Command::Delete => {
+                self.document.delete_rows(first..last.saturating_add(1));
+                self.selection_anchor = None;
+                self.cursor_position.y = cmp::min(first, self.document.len());
+                self.move_cursor(Key::Null);
+            },
+            Command::Substitute { pattern, replacement, global } => {
+                let pattern = Regex::new(&pattern).map_err(|error| format!("invalid regex: {error}"))?;
+                let mut count = 0_usize;
+                for y in first..=last {
+                    let Some(line) = self.document.row(y).map(|row| row.as_str().to_string()) else {
+                        continue;
+                    };
+                    let replaced = if global {
+                        pattern.replace_all(&line, replacement.as_str())
+                    } else {
+                        pattern.replacen(&line, 1, replacement.as_str())
+                    };
+                    if replaced != line {
+                        count = count.saturating_add(1);
+                        self.document.replace_row(y, &replaced);
+                    }
+                }
+                self.move_cursor(Key::Null);
+                self.status_message = StatusMessage::from(format!("substituted on {count} lines"));
+            },
+            Command::GoTo => {
+                self.selection_anchor = None;
+                self.cursor_position = Position { x: 0, y: last };
+            },
+        }
+        Ok(())
+    }
+
+    // quitting leaves unsaved changes behind only when forced
+    fn quit(&mut self, force: bool) -> Result<(), String> {
+        let dirty = self.dirty_buffers();
+        if !force && !dirty.is_empty() {
+            return Err(format!("unsaved changes in {} (add ! to override)", dirty.join(", ")));
+        }
+        self.should_quit = true;
+        Ok(())
+    }
+
+    // reads the file of the active buffer again
+    fn reload(&mut self, force: bool) -> Result<(), String> {
+        let filename = self.document.filename.clone().ok_or("no file name")?;
+        if !force && self.document.is_dirty() {
+            return Err(format!("unsaved changes in {filename} (add ! to override)"));
+        }
+        self.document = Document::open(&filename).map_err(|error| format!("could not open file {filename}: {error}"))?;
+        self.selection_anchor = None;
+        self.move_cursor(Key::Null);
+        self.status_message = StatusMessage::from(format!("reloaded {filename}"));
+        Ok(())
+    }
+
+    // `filetype=name` (or `ft=name`), `subword` and `nosubword`
+    fn set_option(&mut self, option: &str) -> Result<(), String> {
+        let (name, value) = option.split_once('=').map_or((option, None), |(name, value)| (name.trim(), Some(value.trim())));
+        match (name, value) {
+            ("filetype" | "ft", Some(value)) => {
+                if !self.document.set_filetype(value) {
+                    return Err(format!("unknown filetype {value}"));
+                }
+            },
+            ("subword", None | Some("on" | "true")) => self.subword_motion = true,
+            ("nosubword", None) | ("subword", Some("off" | "false")) => self.subword_motion = false,
+            _ => return Err(format!("unknown option {option}")),
+        }
+        Ok(())
+    }
+
+    // shows the sidebar and focuses it, or hides it when already focused
+    fn toggle_explorer(&mut self) {
+        if self.sidebar == Sidebar::Focused {
+            self.sidebar = Sidebar::Hidden;
+        } else {
+            self.explorer.get_or_insert_with(|| Explorer::open(Path::new(".")));
+            self.sidebar = Sidebar::Focused;
+        }
+    }
+
+    fn explorer_key(&mut self, press: Key) {
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        let selected = explorer.selected_entry().map(|entry| (entry.path.clone(), entry.is_dir));
+        match (press, selected) {
+            (Key::Up | Key::Down, _) => explorer.move_selection(press == Key::Up),
+            (Key::Left, _) => explorer.collapse(),
+            (Key::Right, Some((path, true))) if !explorer.is_expanded(&path) => explorer.toggle(),
+            (Key::Char('\n'), Some((_, true))) => explorer.toggle(),
+            (Key::Char('\n') | Key::Right, Some((path, false))) => {
+                self.sidebar = Sidebar::Shown;
+                self.open_path(relative_name(&path));
+            },
+            (Key::Char('.'), _) => explorer.toggle_hidden(),
+            (Key::Char('R'), _) => explorer.refresh(),
+            (Key::Char('a'), _) => self.explorer_create(),
+            (Key::Char('r'), Some((path, _))) => self.explorer_rename(&path),
+            (Key::Char('d'), Some((path, _))) => self.explorer_delete(&path),
+            (Key::Esc, _) => self.sidebar = Sidebar::Shown,
+            _ => (),
+        }
+    }
+
+    fn explorer_create(&mut self) {
+        let Some(name) = self.prompt("new file (end with / for a directory): ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        self.status_message = match explorer.create(&name) {
+            Ok(path) => StatusMessage::from(format!("created {}", relative_name(&path))),
+            Err(error) => StatusMessage::from(format!("ERR: could not create {name}: {error}")),
+        };
+    }
+
+    fn explorer_rename(&mut self, path: &Path) {
+        let old_name = relative_name(path);
+        let Some(name) = self.prompt(&format!("rename {old_name} to: "), |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        // looked up before the old path is gone
+        let moved = self.buffers_under(path);
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        match explorer.rename(path, &name) {
+            Ok(new_path) => {
+                // open buffers follow the file, or the files in the directory, to the new name
+                for (buffer, below) in moved {
+                    let moved_path = if below.as_os_str().is_empty() { new_path.clone() } else { new_path.join(below) };
+                    self.document_mut(buffer).filename = Some(relative_name(&moved_path));
+                }
+                self.status_message = StatusMessage::from(format!("renamed {old_name} to {}", relative_name(&new_path)));
+            },
+            Err(error) => self.status_message = StatusMessage::from(format!("ERR: could not rename {old_name}: {error}")),
+        }
+    }
+
+    fn explorer_delete(&mut self, path: &Path) {
+        let name = relative_name(path);
+        let answer = self.prompt(&format!("delete {name}? (y/n): "), |_, _, _| {}).unwrap_or(None);
+        if answer.as_deref() != Some("y") {
+            return;
+        }
+        let orphaned = self.buffers_under(path);
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        if let Err(error) = explorer.delete(path) {
+            self.status_message = StatusMessage::from(format!("ERR: could not delete {name}: {error}"));
+            return;
+        }
+        // what the open buffers hold is only in the editor now, so they
+        // count as unsaved instead of quietly writing the file back
+        for (buffer, _) in &orphaned {
+            self.document_mut(*buffer).mark_dirty();
+        }
+        self.status_message = StatusMessage::from(if orphaned.is_empty() {
+            format!("deleted {name}")
+        } else {
+            format!("deleted {name}, {} open buffers of it are now unsaved", orphaned.len())
+        });
+    }
+
+    // the buffers of `path` or of files below it, with the rest of their path
+    fn buffers_under(&self, path: &Path) -> Vec<(usize, PathBuf)> {
+        (0..self.buffers.len())
+            .filter_map(|buffer| {
+                let filename = self.document(buffer).filename.as_deref()?;
+                path_below(Path::new(filename), path).map(|below| (buffer, below))
+            })
+            .collect()
+    }
+
+    // the text of every buffer with a file, under the file's canonical path
+    fn open_files(&self) -> Vec<(PathBuf, String)> {
+        (0..self.buffers.len())
+            .filter_map(|buffer| {
+                let document = self.document(buffer);
+                let path = fs::canonicalize(document.filename.as_deref()?).ok()?;
+                Some((path, document.text()))
+            })
+            .collect()
+    }
+
+    // the buffer of `filename`, however the path to it is written
+    fn find_buffer(&self, filename: &str) -> Option<usize> {
+        let shows = |document: &Document| document.filename.as_deref().is_some_and(|name| same_file(name, filename));
+        if shows(&self.document) {
+            return Some(self.current_buffer);
+        }
+        self.buffers.iter().position(|buffer| shows(&buffer.document))
+    }
+
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current_buffer || index >= self.buffers.len() {
+            return;
+        }
+        let active = Buffer {
+            document: mem::take(&mut self.document),
+            cursor_position: mem::take(&mut self.cursor_position),
+            offset: mem::take(&mut self.offset),
+            selection_anchor: self.selection_anchor.take(),
+        };
+        if let Some(slot) = self.buffers.get_mut(self.current_buffer) {
+            *slot = active;
+        }
+        let buffer = self.buffers.get_mut(index).map(mem::take).unwrap_or_default();
+        self.current_buffer = index;
+        self.activate(buffer);
+    }
+
+    fn activate(&mut self, buffer: Buffer) {
+        self.document = buffer.document;
+        self.cursor_position = buffer.cursor_position;
+        self.offset = buffer.offset;
+        self.selection_anchor = buffer.selection_anchor;
+        self.desired_column = None;
+    }
+
+    fn close_buffer(&mut self) {
+        if self.document.is_dirty() {
+            let prompt = format!("{} has unsaved changes, close anyway? (y/n): ", buffer_name(&self.document));
+            if self.prompt(&prompt, |_, _, _| {}).unwrap_or(None).as_deref() != Some("y") {
+                return;
+            }
+        }
+        let closed = self.current_buffer;
+        if self.buffers.len() > 1 {
+            self.buffers.remove(closed);
+        }
+        self.current_buffer = cmp::min(closed, self.buffers.len().saturating_sub(1));
+        // windows on the closed buffer move to the one taking its place
+        for view in &mut self.windows {
+            match view.buffer.cmp(&closed) {
+                Ordering::Equal => *view = View { buffer: self.current_buffer, ..View::default() },
+                Ordering::Greater => view.buffer = view.buffer.saturating_sub(1),
+                Ordering::Less => (),
+            }
+        }
+        let buffer = self.buffers.get_mut(self.current_buffer).map(mem::take).unwrap_or_default();
+        self.activate(buffer);
+    }
+
+    // lists the buffers in the prompt and takes a number or part of a name
+    fn switch_buffer_prompt(&mut self) {
+        let names: Vec<String> = (0..self.buffers.len()).map(|index| {
+            let document = if index == self.current_buffer { &self.document } else { &self.buffers[index].document };
+            let modified = if document.is_dirty() { "+" } else { "" };
+            format!("{}:{}{modified}", index.saturating_add(1), buffer_name(document))
+        }).collect();
+        let prompt = format!("[{}] buffer: ", names.join(" "));
+        let Some(choice) = self.prompt(&prompt, |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let index = choice.parse::<usize>().ok()
+            .and_then(|number| number.checked_sub(1))
+            .or_else(|| names.iter().position(|name| name.contains(&choice)));
+        match index {
+            Some(index) if index < self.buffers.len() => self.switch_buffer(index),
+            _ => self.status_message = StatusMessage::from(format!("ERR: no buffer {choice}")),
+        }
+    }
+
+    fn dirty_buffers(&self) -> Vec<String> {
+        self.buffers.iter()
+            .enumerate()
+            .map(|(index, buffer)| if index == self.current_buffer { &self.document } else { &buffer.document })
+            .filter(|document| document.is_dirty())
+            .map(buffer_name)
+            .collect()
+    }
+
+    fn window_operation(&mut self, action: Action) {
+        match action {
+            Action::SplitVertical => self.split_window(Split::Vertical),
+            Action::SplitHorizontal => self.split_window(Split::Horizontal),
+            Action::CloseWindow => self.close_window(),
+            Action::FocusLeft => self.focus_window(Direction::Left),
+            Action::FocusDown => self.focus_window(Direction::Down),
+            Action::FocusUp => self.focus_window(Direction::Up),
+            Action::FocusRight => self.focus_window(Direction::Right),
+            Action::GrowWindow => {
+                self.layout.resize(self.current_window, RESIZE_STEP);
+            },
+            Action::ShrinkWindow => {
+                self.layout.resize(self.current_window, RESIZE_STEP.saturating_neg());
+            },
+            _ => (),
+        }
+    }
+
+    // the new window starts out showing the same place as the focused one
+    fn split_window(&mut self, split: Split) {
+        let window = self.windows.len();
+        self.windows.push(self.view(self.current_window));
+        self.layout.split(self.current_window, window, split);
+    }
+
+    fn close_window(&mut self) {
+        if !self.layout.remove(self.current_window) {
+            self.status_message = StatusMessage::from("ERR: cannot close the last window".to_string());
+            return;
+        }
+        self.windows.remove(self.current_window);
+        self.current_window = cmp::min(self.current_window, self.windows.len().saturating_sub(1));
+        let view = self.windows.get_mut(self.current_window).map(mem::take).unwrap_or_default();
+        self.show_view(view);
+    }
+
+    fn focus_window(&mut self, direction: Direction) {
+        let (rects, _) = self.window_rects();
+        let Some(window) = window::neighbour(&rects, self.window_rect(), direction) else {
+            return;
+        };
+        let view = self.view(self.current_window);
+        if let Some(slot) = self.windows.get_mut(self.current_window) {
+            *slot = view;
+        }
+        let view = self.windows.get_mut(window).map(mem::take).unwrap_or_default();
+        self.current_window = window;
+        self.show_view(view);
+    }
+
+    fn show_view(&mut self, view: View) {
+        self.switch_buffer(view.buffer);
+        self.cursor_position = view.cursor_position;
+        self.offset = view.offset;
+        self.selection_anchor = view.selection_anchor;
+        self.move_cursor(Key::Null);
+    }
+
+    fn view(&self, window: usize) -> View {
+        if window == self.current_window {
+            View {
+                buffer: self.current_buffer,
+                cursor_position: self.cursor_position.clone(),
+                offset: self.offset.clone(),
+                selection_anchor: self.selection_anchor.clone(),
+            }
+        } else {
+            self.windows.get(window).cloned().unwrap_or_default()
+        }
+    }
+
+    fn document(&self, buffer: usize) -> &Document {
+        match self.buffers.get(buffer) {
+            Some(other) if buffer != self.current_buffer => &other.document,
+            _ => &self.document,
+        }
+    }
+
+    fn document_mut(&mut self, buffer: usize) -> &mut Document {
+        match self.buffers.get_mut(buffer) {
+            Some(other) if buffer != self.current_buffer => &mut other.document,
+            _ => &mut self.document,
+        }
+    }
+
+    // window rects cover the terminal but for the message bar, each
+    // window's last row being its status line
+    fn window_rects(&self) -> (Vec<(usize, Rect)>, Vec<Rect>) {
+        let size = self.terminal.size();
+        let sidebar = self.explorer_rect().map_or(0, |rect| rect.width.saturating_add(1));
+        let area = Rect {
+            x: sidebar,
+            y: 0,
+            width: (size.width as usize).saturating_sub(sidebar),
+            height: (size.height as usize).saturating_add(1),
+        };
+        let mut windows = Vec::new();
+        let mut separators = Vec::new();
+        self.layout.rects(area, &mut windows, &mut separators);
+        (windows, separators)
+    }
+
+    // the sidebar takes the left of the terminal, up to a third of it
+    #[allow(clippy::integer_division)]
+    fn explorer_rect(&self) -> Option<Rect> {
+        if self.sidebar == Sidebar::Hidden || self.explorer.is_none() {
+            return None;
+        }
+        let size = self.terminal.size();
+        Some(Rect {
+            x: 0,
+            y: 0,
+            width: cmp::min(EXPLORER_WIDTH, size.width as usize / 3),
+            height: (size.height as usize).saturating_add(1),
+        })
+    }
+
+    fn window_rect(&self) -> Rect {
+        let (rects, _) = self.window_rects();
+        rects.into_iter()
+            .find(|(window, _)| *window == self.current_window)
+            .map(|(_, rect)| rect)
+            .unwrap_or_default()
+    }
+
+    // start and end of the selection, in document order
+    fn selection(&self) -> Option<(Position, Position)> {
+        ordered_selection(self.selection_anchor.as_ref(), &self.cursor_position)
+    }
+
+    fn line_operation(&mut self, action: Action) {
+        match action {
+            Action::DuplicateLines => {
+                let rows = self.selected_rows();
+                self.document.duplicate_rows(rows.clone());
+                self.shift_rows(rows.len(), SearchDirection::Forward);
+            },
+            Action::DeleteLines => {
+                let rows = self.selected_rows();
+                self.document.delete_rows(rows.clone());
+                self.selection_anchor = None;
+                self.cursor_position.y = cmp::min(rows.start, self.document.len());
+                self.move_cursor(Key::Null);
+            },
+            Action::MoveLinesUp | Action::MoveLinesDown => {
+                let direction = if action == Action::MoveLinesUp { SearchDirection::Backward } else { SearchDirection::Forward };
+                if self.document.move_rows(self.selected_rows(), direction) {
+                    self.shift_rows(1, direction);
+                }
+            },
+            Action::JoinLines => self.document.join_rows(self.cursor_position.y),
+            Action::InsertLineBelow => self.document.insert_blank_row(self.cursor_position.y.saturating_add(1)),
+            Action::InsertLineAbove => {
+                self.document.insert_blank_row(self.cursor_position.y);
+                self.shift_rows(1, SearchDirection::Forward);
+            },
+            Action::SortLines | Action::SortLinesUnique => self.document.sort_rows(self.selected_rows(), action == Action::SortLinesUnique),
+            _ => (),
+        }
+    }
+
+    // rows the line operations act on, a selection ending at column 0
+    // leaves that row alone
+    fn selected_rows(&self) -> Range<usize> {
+        let len = self.document.len();
+        let rows = match self.selection() {
+            Some((start, end)) if end.x == 0 => start.y..cmp::max(end.y, start.y.saturating_add(1)),
+            Some((start, end)) => start.y..end.y.saturating_add(1),
+            None => self.cursor_position.y..self.cursor_position.y.saturating_add(1),
+        };
+        cmp::min(rows.start, len)..cmp::min(rows.end, len)
+    }
+
+    // follows rows that moved by `count`
+    fn shift_rows(&mut self, count: usize, direction: SearchDirection) {
+        for position in [Some(&mut self.cursor_position), self.selection_anchor.as_mut()].into_iter().flatten() {
+            position.y = match direction {
+                SearchDirection::Forward => position.y.saturating_add(count),
+                SearchDirection::Backward => position.y.saturating_sub(count),
+            };
+        }
+    }
+
+    fn delete_selection(&mut self) {
+        if let Some((start, end)) = self.selection() {
+            self.document.delete_range(&start, &end);
+            self.cursor_position = start;
+        }
+        self.selection_anchor = None;
+    }
+
+    // an opener wraps the selection in its pair, anything else replaces it
+    fn type_over_selection(&mut self, c: char) {
+        let Some((start, end)) = self.selection() else {
+            return;
+        };
+        if self.document.wrap(&start, &end, c) {
+            let shift = |position: Position| Position {
+                x: if position.y == start.y { position.x.saturating_add(1) } else { position.x },
+                y: position.y,
+            };
+            let anchor_first = self.selection_anchor.as_ref().is_some_and(|anchor| anchor.x == start.x && anchor.y == start.y);
+            let (start, end) = (shift(start.clone()), shift(end));
+            (self.selection_anchor, self.cursor_position) = if anchor_first { (Some(start), end) } else { (Some(end), start) };
+            return;
+        }
+        self.delete_selection();
+        self.process_char(c);
+    }
+
+    fn process_char(&mut self, c: char) {
+        self.cursor_position = if c == '\n' {
+            self.document.newline(&self.cursor_position)
+        } else {
+            self.document.type_char(&self.cursor_position, c)
+        };
+    }
+
+    // line comments act on every selected row, block comments on the
+    // selection or else the current row
+    fn toggle_comment(&mut self, block: bool) {
+        let y = self.cursor_position.y;
+        let old_len = self.document.row(y).map_or(0, Row::len);
+        let toggled = match (block, self.selection()) {
+            (false, _) => self.document.toggle_line_comment(self.selected_rows()),
+            (true, Some((start, end))) => self.document.toggle_block_comment(&start, &end),
+            (true, None) => {
+                let indent = self.document.row(y).map_or(0, |row| row.indentation().chars().count());
+                self.document.toggle_block_comment(&Position { x: indent, y }, &Position { x: old_len, y })
+            },
+        };
+        if !toggled {
+            self.status_message = StatusMessage::from(format!("{} has no {} comments.",
+                self.document.filetype(), if block { "block" } else { "line" }));
+            return;
+        }
+        // keep the cursor on the same text
+        let new_len = self.document.row(y).map_or(0, Row::len);
+        let x = self.cursor_position.x;
+        self.cursor_position.x = if new_len >= old_len {
+            x.saturating_add(new_len.saturating_sub(old_len))
+        } else {
+            x.saturating_sub(old_len.saturating_sub(new_len))
+        };
+        self.selection_anchor = None;
+    }
+
+    fn jump_to_bracket(&mut self) {
+        let partner = self.document.find_bracket(&self.cursor_position)
+            .and_then(|bracket| self.document.bracket_partner(&bracket));
+        if let Some(partner) = partner {
+            self.cursor_position = partner;
+        } else {
+            self.status_message = StatusMessage::from("no matching bracket.".to_string());
+        }
+    }
+
+    fn go_to(&mut self) {
+        let Some(input) = self.prompt("go to (line, line:col, +N, -N, N%): ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        if let Some(position) = parse_position(&input, &self.cursor_position, self.document.len()) {
+            self.selection_anchor = None;
+            self.cursor_position = position;
+            self.move_cursor(Key::Null);
+        } else {
+            self.status_message = StatusMessage::from(format!("ERR: invalid position {input}"));
+        }
+    }
+
+    fn set_filetype(&mut self) {
+        let Some(name) = self.prompt("filetype: ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        self.status_message = if self.document.set_filetype(&name) {
+            StatusMessage::from(format!("filetype set to {}.", self.document.filetype()))
+        } else {
+            StatusMessage::from(format!("ERR: unknown filetype {name}"))
+        };
+    }
+
+    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error> where C: FnMut(&mut Self, Key, &mut String), {
         let mut res = String::new();
         loop {
-            self.status_message = StatusMessage::from(format!("{}{}", prompt, res));
+            self.status_message = StatusMessage::from(format!("{prompt}{res}"));
             self.refresh_screen()?;
             let key = Terminal::read_key()?;
             match key {
                 Key::Backspace => {
-                    if !res.is_empty() {
-                        res.truncate(res.len().saturating_sub(1))
-                    }
-                }
-                Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        res.push(c);
-                    }
+                    res.pop();
                 },
+                Key::Char('\n') => break,
+                Key::Char(c) if !c.is_control() => res.push(c),
+                // the callback still hears of it, an empty answer alone
+                // does not tell it apart from enter
                 Key::Esc => {
                     res.truncate(0);
+                    callback(self, key, &mut res);
                     break;
                 }
                 _ => (),
             }
-            callback(self, key, &res);
+            callback(self, key, &mut res);
         }
         self.status_message = StatusMessage::from(String::new());
         if res.is_empty() {
@@ -186,86 +1217,332 @@ impl Editor {
             Terminal::clear_screen();
             println!("heitx terminal exit...\r");
         } else {
-            self.document.highlight(&self.highlighted_word,
-                                    Some(self.offset.y.saturating_add(self.terminal.size().height as usize)));
-            self.draw_rows();
-            self.draw_status_view();
+            if self.finder.is_some() || self.grep.is_some() || self.replace.is_some() || self.palette.is_some() {
+                if self.palette.is_some() {
+                    self.draw_palette();
+                } else if self.replace.is_some() {
+                    self.draw_replace();
+                } else if self.grep.is_some() {
+                    self.draw_grep();
+                } else {
+                    self.draw_finder();
+                }
+                self.draw_message_bar();
+                Terminal::cursor_show();
+                return Terminal::flush();
+            }
+            let (windows, separators) = self.window_rects();
+            for (window, rect) in windows {
+                self.draw_window(window, rect);
+            }
+            for separator in separators {
+                draw_separator(separator);
+            }
+            let explorer_offset = self.draw_explorer();
             self.draw_message_bar();
-            Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
-            });
+            let rect = self.window_rect();
+            if let (Sidebar::Focused, Some(explorer)) = (self.sidebar, &self.explorer) {
+                Terminal::cursor_position(&Position { x: 0, y: explorer.selected().saturating_sub(explorer_offset) });
+            } else {
+                Terminal::cursor_position(&Position {
+                    x: rect.x.saturating_add(self.cursor_position.x.saturating_sub(self.offset.x)),
+                    y: rect.y.saturating_add(self.cursor_position.y.saturating_sub(self.offset.y)),
+                });
+            }
         }
         Terminal::cursor_show();
         Terminal::flush()
     }
 
     fn draw_message_bar(&self) {
+        Terminal::cursor_position(&Position { x: 0, y: (self.terminal.size().height as usize).saturating_add(1) });
         Terminal::clear_current_line();
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
+        if message.time.elapsed() < Duration::new(5, 0) {
             let mut text = message.text.clone();
             text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+            print!("{text}");
         }
     }
 
-    fn draw_status_view(&self) {
+    // the list of matches on the left, the selected file on the right
+    #[allow(clippy::integer_division)]
+    fn draw_finder(&mut self) {
+        let size = self.terminal.size();
+        let width = size.width as usize;
+        let height = size.height as usize;
+        let Some(finder) = &mut self.finder else {
+            return;
+        };
+        finder.poll();
+        let list_width = width / 2;
+        let preview_width = width.saturating_sub(list_width).saturating_sub(1);
+        let selected = finder.selected_match().map(|found| found.path.clone());
+        if selected.as_deref() != self.preview.as_ref().and_then(|preview| preview.filename.as_deref()) {
+            self.preview = selected.and_then(|path| Document::open(&path).ok());
+        }
+        if let Some(preview) = &mut self.preview {
+            preview.highlight(&None, 0, height);
+        }
+        let Some(finder) = &self.finder else {
+            return;
+        };
+        let offset = finder.selected().saturating_sub(height.saturating_sub(1));
+        for line in 0..height {
+            Terminal::cursor_position(&Position { x: 0, y: line });
+            let index = offset.saturating_add(line);
+            if let Some(found) = finder.matches().get(index) {
+                draw_match(&found.path, &found.positions, list_width, index == finder.selected());
+            } else {
+                print!("{:list_width$}", "");
+            }
+            print!("│");
+            match self.preview.as_ref().and_then(|preview| preview.row(line)) {
+                Some(row) => draw_row(row, 0, preview_width),
+                None => print!("{:preview_width$}", ""),
+            }
+        }
+        let walking = if finder.is_walking() { ", scanning" } else { "" };
+        draw_panel_status(&format!("{}/{} files{walking}", finder.matches().len(), finder.file_count()), height, width);
+    }
+
+    // every action matching the query with its binding and description
+    fn draw_palette(&self) {
+        let size = self.terminal.size();
+        let width = size.width as usize;
+        let height = size.height as usize;
+        let Some(palette) = &self.palette else {
+            return;
+        };
+        let name_width = action::ACTIONS.iter().map(|entry| entry.name.len()).max().unwrap_or_default().saturating_add(2);
+        let binding_width = 16;
+        let description_width = width.saturating_sub(name_width).saturating_sub(binding_width);
+        let offset = palette.selected().saturating_sub(height.saturating_sub(1));
+        for line in 0..height {
+            Terminal::cursor_position(&Position { x: 0, y: line });
+            let index = offset.saturating_add(line);
+            let Some(found) = palette.matches().get(index) else {
+                print!("{:width$}", "");
+                continue;
+            };
+            let selected = index == palette.selected();
+            draw_match(found.entry.name, &found.positions, name_width, selected);
+            if selected {
+                print!("{}", style::Invert);
+            }
+            let description: String = found.entry.description.chars().take(description_width).collect();
+            print!("{:binding_width$}{description:description_width$}{}", found.entry.binding(), style::NoInvert);
+        }
+        draw_panel_status(&format!("{}/{} actions", palette.matches().len(), action::ACTIONS.len()), height, width);
+    }
+
+    // the hits grouped by file, each with its line
+    fn draw_grep(&self) {
+        let size = self.terminal.size();
+        let width = size.width as usize;
+        let height = size.height as usize;
+        let Some(grep) = &self.grep else {
+            return;
+        };
+        let lines = grep.lines();
+        let selected_line = lines.iter()
+            .position(|line| matches!(line, grep::Line::Hit(index) if *index == grep.selected()))
+            .unwrap_or(0);
+        let offset = selected_line.saturating_sub(height.saturating_sub(1));
+        for screen_line in 0..height {
+            Terminal::cursor_position(&Position { x: 0, y: screen_line });
+            match lines.get(offset.saturating_add(screen_line)) {
+                Some(grep::Line::File(path, count)) => {
+                    let text: String = format!("{path} ({count})").chars().take(width).collect();
+                    print!("{}{text:width$}{}", style::Bold, style::Reset);
+                },
+                Some(grep::Line::Hit(index)) => {
+                    if let Some(hit) = grep.hits().get(*index) {
+                        draw_hit(hit, width, *index == grep.selected());
+                    }
+                },
+                None => print!("{:width$}", ""),
+            }
+        }
+        let searching = if grep.is_searching() { ", searching" } else { "" };
+        draw_panel_status(&format!("{} matches{searching}", grep.hits().len()), height, width);
+    }
+
+    // every change as the line before and after it, grouped by file
+    fn draw_replace(&self) {
+        let size = self.terminal.size();
+        let width = size.width as usize;
+        let height = size.height as usize;
+        let Some(replace) = &self.replace else {
+            return;
+        };
+        let lines = replace.lines();
+        let selected_line = lines.iter()
+            .position(|line| matches!(line, replace::Line::Added(index) if *index == replace.selected()))
+            .unwrap_or(0);
+        let offset = selected_line.saturating_sub(height.saturating_sub(1));
+        for screen_line in 0..height {
+            Terminal::cursor_position(&Position { x: 0, y: screen_line });
+            match lines.get(offset.saturating_add(screen_line)) {
+                Some(replace::Line::File(path, included, total)) => {
+                    let text: String = format!("{path} ({included}/{total})").chars().take(width).collect();
+                    print!("{}{text:width$}{}", style::Bold, style::Reset);
+                },
+                Some(replace::Line::Removed(index) | replace::Line::Added(index)) => {
+                    let Some(change) = replace.changes().get(*index) else {
+                        continue;
+                    };
+                    let added = matches!(lines.get(offset.saturating_add(screen_line)), Some(replace::Line::Added(_)));
+                    let mark = match (added, change.included) {
+                        (false, true) => "[x]",
+                        (false, false) => "[ ]",
+                        (true, _) => "   ",
+                    };
+                    let (sign, line) = if added { ('+', change.line_after()) } else { ('-', change.hit.line.clone()) };
+                    let text: String = format!("{mark} {:>5} {sign} {}", change.hit.position.y.saturating_add(1), line.replace('\t', " "))
+                        .chars()
+                        .take(width)
+                        .collect();
+                    if *index == replace.selected() {
+                        print!("{}", style::Invert);
+                    }
+                    if !change.included {
+                        print!("{text:width$}");
+                    } else if added {
+                        print!("{}{text:width$}{}", color::Fg(color::Green), color::Fg(color::Reset));
+                    } else {
+                        print!("{}{text:width$}{}", color::Fg(color::Red), color::Fg(color::Reset));
+                    }
+                    print!("{}", style::NoInvert);
+                },
+                None => print!("{:width$}", ""),
+            }
+        }
+        let included = replace.changes().iter().filter(|change| change.included).count();
+        draw_panel_status(&format!("{included}/{} matches to replace", replace.changes().len()), height, width);
+    }
+
+    // returns the first visible line of the tree
+    fn draw_explorer(&mut self) -> usize {
+        let Some(rect) = self.explorer_rect() else {
+            return 0;
+        };
+        let focused = self.sidebar == Sidebar::Focused;
+        let Some(explorer) = &mut self.explorer else {
+            return 0;
+        };
+        let height = rect.height.saturating_sub(1);
+        let offset = explorer.scroll(height);
+        let width = rect.width;
+        for line in 0..height {
+            Terminal::cursor_position(&Position { x: rect.x, y: rect.y.saturating_add(line) });
+            let index = offset.saturating_add(line);
+            let Some(entry) = explorer.entries().get(index) else {
+                print!("{:width$}", "");
+                continue;
+            };
+            let marker = match (entry.is_dir, explorer.is_expanded(&entry.path)) {
+                (true, true) => "▾ ",
+                (true, false) => "▸ ",
+                (false, _) => "  ",
+            };
+            let slash = if entry.is_dir { "/" } else { "" };
+            let text: String = format!("{}{marker}{}{slash}", "  ".repeat(entry.depth), entry.name()).chars().take(width).collect();
+            if index == explorer.selected() && focused {
+                print!("{}{text:width$}{}", style::Invert, style::NoInvert);
+            } else {
+                print!("{text:width$}");
+            }
+        }
+        let hidden = if explorer.shows_hidden() { " [hidden]" } else { "" };
+        let status: String = format!("{}{hidden}", explorer.root().display()).chars().take(width).collect();
+        Terminal::cursor_position(&Position { x: rect.x, y: rect.y.saturating_add(height) });
+        if focused {
+            Terminal::set_bg_color(STATUS_BG_COLOR);
+        } else {
+            Terminal::set_bg_color(INACTIVE_STATUS_BG_COLOR);
+        }
+        Terminal::set_fg_color(STATUS_FG_COLOR);
+        print!("{status:width$}");
+        Terminal::reset_fg_color();
+        Terminal::reset_bg_color();
+        draw_separator(Rect { x: rect.width, width: 1, ..rect });
+        offset
+    }
+
+    fn draw_window(&mut self, window: usize, rect: Rect) {
+        let view = self.view(window);
+        let height = rect.height.saturating_sub(1);
+        let word = self.highlighted_word.clone();
+        let selection = ordered_selection(view.selection_anchor.as_ref(), &view.cursor_position);
+        let document = self.document_mut(view.buffer);
+        document.highlight(&word, view.offset.y, view.offset.y.saturating_add(height));
+        document.highlight_brackets(&view.cursor_position, view.offset.y..view.offset.y.saturating_add(height));
+        document.highlight_selection(selection.as_ref());
+        self.draw_rows(Rect { height, ..rect }, &view);
+        self.draw_status_view(Rect { y: rect.y.saturating_add(height), height: 1, ..rect }, &view, window == self.current_window);
+    }
+
+    fn draw_status_view(&self, rect: Rect, view: &View, focused: bool) {
+        if rect.height == 0 {
+            return;
+        }
         let mut status;
-        let width = self.terminal.size().width as usize;
-        let mod_indicator = if self.document.is_dirty() {
+        let width = rect.width;
+        let document = self.document(view.buffer);
+        let mod_indicator = if document.is_dirty() {
             "(modified)"
         } else {
             ""
         };
-        let mut filename = "[No Name]".to_string();
-        if let Some(name) = &self.document.filename {
-            filename = name.clone();
-            filename.truncate(20);
+        let mut filename = buffer_name(document);
+        filename.truncate(20);
+        status = format!("{} - {} lines{}", filename, document.len(), mod_indicator);
+        if self.buffers.len() > 1 {
+            status = format!("[{}/{}] {status}", view.buffer.saturating_add(1), self.buffers.len());
         }
-        status = format!("{} - {} lines{}", filename, self.document.len(), mod_indicator);
         let line_indict = format!(
             "{} | {}/{}",
-            self.document.filetype(),
-            self.cursor_position.y.saturating_add(1),
-            self.document.len());
+            document.filetype(),
+            view.cursor_position.y.saturating_add(1),
+            document.len());
         let len = status.len() + line_indict.len();
         if width > len {
             status.push_str(&" ".repeat(width.saturating_sub(len)));
+        } else {
+            // narrow windows keep the position and drop the end of the name
+            status.truncate(width.saturating_sub(line_indict.len()));
         }
-        status = format!("{}{}", status, line_indict);
+        status = format!("{status}{line_indict}");
         status.truncate(width);
-        Terminal::set_bg_color(STATUS_BG_COLOR);
+        Terminal::cursor_position(&Position { x: rect.x, y: rect.y });
+        if focused {
+            Terminal::set_bg_color(STATUS_BG_COLOR);
+        } else {
+            Terminal::set_bg_color(INACTIVE_STATUS_BG_COLOR);
+        }
         Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{}\r", status);
+        print!("{status}");
         Terminal::reset_fg_color();
         Terminal::reset_bg_color();
     }
 
-    fn draw_rows(&self) {
-        let height = self.terminal.size().height;
-        for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self.document.row(self.offset.y.saturating_add(terminal_row as usize)) {
-                self.draw_row(row);
-            } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_info();
+    fn draw_rows(&self, rect: Rect, view: &View) {
+        let document = self.document(view.buffer);
+        for terminal_row in 0..rect.height {
+            Terminal::cursor_position(&Position { x: rect.x, y: rect.y.saturating_add(terminal_row) });
+            if let Some(row) = document.row(view.offset.y.saturating_add(terminal_row)) {
+                draw_row(row, view.offset.x, rect.width);
+            } else if document.is_empty() && terminal_row == rect.height / 3 {
+                draw_welcome_info(rect.width);
             } else {
-                println!("~\r");
-            };
+                print!("{:<width$}", "~", width = rect.width);
+            }
         }
     }
 
-    pub fn draw_row(&self, row: &Row) {
-        let width = self.terminal.size().width as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(width);
-        let row = row.render(start, end);
-        println!("{}\r", row);
-    }
-
     fn move_cursor(&mut self, key: Key) {
-        let terminal_height = self.terminal.size().height as usize;
+        let terminal_height = self.window_rect().height.saturating_sub(1);
         let Position { mut y, mut x } = self.cursor_position;
         let height = self.document.len();
         let mut width = if let Some(row) = self.document.row(y) {
@@ -273,13 +1550,12 @@ impl Editor {
         } else {
             0
         };
+        if matches!(key, Key::Up | Key::Down | Key::PageUp | Key::PageDown) {
+            x = *self.desired_column.get_or_insert(x);
+        }
         match key {
             Key::Up => y = y.saturating_sub(1),
-            Key::Down => {
-                if y < height {
-                    y = y.saturating_add(1);
-                }
-            },
+            Key::Down if y < height => y = y.saturating_add(1),
             // moving left at the start of a line
             Key::Left => {
                 if x > 0 {
@@ -334,9 +1610,10 @@ impl Editor {
 
     fn scroll(&mut self) {
         let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
-        let mut offset = &mut self.offset;
+        let rect = self.window_rect();
+        let width = rect.width;
+        let height = rect.height.saturating_sub(1);
+        let offset = &mut self.offset;
         if y < offset.y {
             offset.y = y;
         } else if y >= offset.y.saturating_add(height) {
@@ -349,18 +1626,6 @@ impl Editor {
         }
     }
 
-    fn draw_welcome_info(&self) {
-        let mut welcome_message = format!("heitx editor --version {}", VERSION);
-        let width = self.terminal.size().width as usize;
-        let len = welcome_message.len();
-        #[allow(clippy::integer_arithmetic, clippy::integer_division)]
-        let padding = width.saturating_sub(len) / 2;
-        let spaces = " ".repeat(padding.saturating_sub(1));
-        welcome_message = format!("~{}{}", spaces, welcome_message);
-        welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
-    }
-
     fn search(&mut self) {
         let old_position = self.cursor_position.clone();
         let mut direction = SearchDirection::Forward;
@@ -374,13 +1639,13 @@ impl Editor {
                 Key::Left | Key::Up => direction = SearchDirection::Backward,
                 _ => direction = SearchDirection::Forward,
             }
-            if let Some(position) = editor.document.search(&query, &editor.cursor_position, direction) {
+            if let Some(position) = editor.document.search(query, &editor.cursor_position, direction) {
                 editor.cursor_position = position;
                 editor.scroll();
             } else if moved {
                 editor.move_cursor(Key::Left);
             }
-            editor.highlighted_word = Some(query.to_string());
+            editor.highlighted_word = Some(query.clone());
         },).unwrap_or(None);
         if query.is_none() {
             self.cursor_position = old_position;
@@ -390,7 +1655,187 @@ impl Editor {
     }
 }
 
+// start and end of the selection between `anchor` and `cursor`, in
+// document order
+fn ordered_selection(anchor: Option<&Position>, cursor: &Position) -> Option<(Position, Position)> {
+    let anchor = anchor?.clone();
+    let cursor = cursor.clone();
+    match (anchor.y, anchor.x).cmp(&(cursor.y, cursor.x)) {
+        Ordering::Less => Some((anchor, cursor)),
+        Ordering::Greater => Some((cursor, anchor)),
+        Ordering::Equal => None,
+    }
+}
+
+// prints the part of `row` from column `start` that fits into `width`,
+// padded so nothing of a neighbouring window shows through
+fn draw_row(row: &Row, start: usize, width: usize) {
+    let end = start.saturating_add(width);
+    let visible = cmp::min(row.len().saturating_sub(start), width);
+    print!("{}{}", row.render(start, end), " ".repeat(width.saturating_sub(visible)));
+}
+
+// a path in the picker with the chars the query matched highlighted
+fn draw_match(path: &str, positions: &[usize], width: usize, selected: bool) {
+    if selected {
+        print!("{}", style::Invert);
+    }
+    let mut len = 0_usize;
+    for (index, c) in path.chars().take(width).enumerate() {
+        if positions.contains(&index) {
+            print!("{}{c}{}", color::Fg(highlighting::Type::Match.to_color()), color::Fg(color::Reset));
+        } else {
+            print!("{c}");
+        }
+        len = len.saturating_add(1);
+    }
+    print!("{:1$}{2}", "", width.saturating_sub(len), style::NoInvert);
+}
+
+// a hit's line number and line, the matched part highlighted
+fn draw_hit(hit: &grep::Hit, width: usize, selected: bool) {
+    if selected {
+        print!("{}", style::Invert);
+    }
+    let prefix = format!("  {}: ", hit.position.y.saturating_add(1));
+    let mut len = prefix.len();
+    print!("{prefix}");
+    let matched = hit.position.x..hit.position.x.saturating_add(hit.len);
+    for (index, grapheme) in hit.line.graphemes(true).enumerate() {
+        if len >= width {
+            break;
+        }
+        let grapheme = if grapheme == "\t" { " " } else { grapheme };
+        if matched.contains(&index) {
+            print!("{}{grapheme}{}", color::Fg(highlighting::Type::Match.to_color()), color::Fg(color::Reset));
+        } else {
+            print!("{grapheme}");
+        }
+        len = len.saturating_add(1);
+    }
+    print!("{:1$}{2}", "", width.saturating_sub(len), style::NoInvert);
+}
+
+fn draw_panel_status(status: &str, y: usize, width: usize) {
+    Terminal::cursor_position(&Position { x: 0, y });
+    Terminal::set_bg_color(STATUS_BG_COLOR);
+    Terminal::set_fg_color(STATUS_FG_COLOR);
+    print!("{status:width$}");
+    Terminal::reset_fg_color();
+    Terminal::reset_bg_color();
+}
+
+fn draw_welcome_info(width: usize) {
+    let mut welcome_message = format!("heitx editor --version {VERSION}");
+    let len = welcome_message.len();
+    #[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+    let padding = width.saturating_sub(len) / 2;
+    let spaces = " ".repeat(padding.saturating_sub(1));
+    welcome_message = format!("~{spaces}{welcome_message}");
+    welcome_message.truncate(width);
+    print!("{welcome_message:<width$}");
+}
+
+fn draw_separator(rect: Rect) {
+    for y in rect.y..rect.y.saturating_add(rect.height) {
+        Terminal::cursor_position(&Position { x: rect.x, y });
+        print!("│");
+    }
+}
+
+// explorer paths under `.` are opened without the leading `./`
+fn relative_name(path: &Path) -> String {
+    path.strip_prefix(".").unwrap_or(path).display().to_string()
+}
+
+// `./src/x.rs`, `src/x.rs` and its absolute path are the same file, as
+// are two links to it; paths to files not yet written are only compared
+// as written
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => Path::new(a).components().filter(|part| *part != Component::CurDir).eq(Path::new(b).components().filter(|part| *part != Component::CurDir)),
+    }
+}
+
+// what is left of `file` below `dir`, empty when it is `dir` itself
+fn path_below(file: &Path, dir: &Path) -> Option<PathBuf> {
+    if let (Ok(file), Ok(dir)) = (fs::canonicalize(file), fs::canonicalize(dir)) {
+        return file.strip_prefix(dir).ok().map(Path::to_path_buf);
+    }
+    let written = |path: &Path| path.components().filter(|part| *part != Component::CurDir).collect::<PathBuf>();
+    written(file).strip_prefix(written(dir)).ok().map(Path::to_path_buf)
+}
+
+fn buffer_name(document: &Document) -> String {
+    document.filename.clone().unwrap_or_else(|| "[No Name]".to_string())
+}
+
+// go-to input as a position: `line`, `line:col`, `+N`/`-N` lines from
+// `current` or `N%` of `rows`, lines and columns counting from 1
+fn parse_position(input: &str, current: &Position, rows: usize) -> Option<Position> {
+    let input = input.trim();
+    let last = rows.saturating_sub(1);
+    if let Some(percent) = input.strip_suffix('%') {
+        let percent: usize = percent.trim().parse().ok()?;
+        let y = last.saturating_mul(cmp::min(percent, 100)) / 100;
+        return Some(Position { x: 0, y });
+    }
+    if let Some(count) = input.strip_prefix('+') {
+        let y = current.y.saturating_add(count.trim().parse().ok()?);
+        return Some(Position { x: current.x, y: cmp::min(y, last) });
+    }
+    if let Some(count) = input.strip_prefix('-') {
+        let y = current.y.saturating_sub(count.trim().parse().ok()?);
+        return Some(Position { x: current.x, y });
+    }
+    let (line, column) = input.split_once(':').unwrap_or((input, "1"));
+    let line: usize = line.trim().parse().ok()?;
+    let column: usize = column.trim().parse().ok()?;
+    Some(Position {
+        x: column.saturating_sub(1),
+        y: cmp::min(line.saturating_sub(1), last),
+    })
+}
+
 fn close(e: &std::io::Error) {
     print!("{}", termion::clear::All);
     panic!("{}", e)
+}
+#[cfg(test)]
+mod tests {
+    use super::{parse_position, Position};
+
+    #[test]
+    fn go_to_positions() {
+        let current = Position { x: 3, y: 10 };
+        // input, rows in the document, expected x and y
+        let cases = [
+            ("7", 50, Some((0, 6))),
+            ("7:5", 50, Some((4, 6))),
+            (" 12 : 3 ", 50, Some((2, 11))),
+            ("3:0", 50, Some((0, 2))),
+            ("0", 50, Some((0, 0))),
+            ("+5", 50, Some((3, 15))),
+            ("-4", 50, Some((3, 6))),
+            ("50%", 50, Some((0, 24))),
+            ("0%", 50, Some((0, 0))),
+            ("100%", 50, Some((0, 49))),
+            // out of range lines are clamped to the document
+            ("100", 50, Some((0, 49))),
+            ("+100", 50, Some((3, 49))),
+            ("-20", 50, Some((3, 0))),
+            ("250%", 50, Some((0, 49))),
+            ("5", 0, Some((0, 0))),
+            ("", 50, None),
+            ("abc", 50, None),
+            ("5:x", 50, None),
+            ("x%", 50, None),
+            ("+", 50, None),
+        ];
+        for (input, rows, expected) in cases {
+            let position = parse_position(input, &current, rows).map(|position| (position.x, position.y));
+            assert_eq!(position, expected, "{input:?} in {rows} rows");
+        }
+    }
 }
\ No newline at end of file
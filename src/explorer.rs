@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use ignore::WalkBuilder;
+
+// a file or directory on a visible line of the tree
+pub struct Entry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub is_dir: bool,
+}
+
+impl Entry {
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(|| self.path.display().to_string(), |name| name.to_string_lossy().to_string())
+    }
+}
+
+// the directory tree of the sidebar, flattened to its visible lines
+pub struct Explorer {
+    root: PathBuf,
+    entries: Vec<Entry>,
+    expanded: HashSet<PathBuf>,
+    show_hidden: bool,
+    selected: usize,
+    offset: usize,
+}
+
+impl Explorer {
+    pub fn open(root: &Path) -> Self {
+        let mut explorer = Self {
+            root: root.to_path_buf(),
+            entries: Vec::new(),
+            expanded: HashSet::new(),
+            show_hidden: false,
+            selected: 0,
+            offset: 0,
+        };
+        explorer.refresh();
+        explorer
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    pub fn is_expanded(&self, path: &Path) -> bool {
+        self.expanded.contains(path)
+    }
+
+    pub fn shows_hidden(&self) -> bool {
+        self.show_hidden
+    }
+
+    // re-reads every expanded directory, keeping the selection on the same path
+    pub fn refresh(&mut self) {
+        let selected = self.selected_entry().map(|entry| entry.path.clone());
+        self.entries.clear();
+        self.list(&self.root.clone(), 0);
+        if let Some(selected) = selected {
+            self.select_path(&selected);
+        }
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+    }
+
+    fn list(&mut self, dir: &Path, depth: usize) {
+        // the walker reads .gitignore files of `dir` and its parents, even
+        // outside of a git repository
+        let walker = WalkBuilder::new(dir)
+            .max_depth(Some(1))
+            .hidden(!self.show_hidden)
+            .require_git(false)
+            .build();
+        let mut children: Vec<(PathBuf, bool)> = walker
+            .filter_map(Result::ok)
+            .filter(|entry| entry.depth() == 1)
+            .map(|entry| (entry.path().to_path_buf(), entry.file_type().is_some_and(|file_type| file_type.is_dir())))
+            .collect();
+        // directories first, then by name
+        children.sort_by(|(a, a_dir), (b, b_dir)| b_dir.cmp(a_dir).then_with(|| a.cmp(b)));
+        for (path, is_dir) in children {
+            let expanded = is_dir && self.expanded.contains(&path);
+            self.entries.push(Entry { path: path.clone(), depth, is_dir });
+            if expanded {
+                self.list(&path, depth.saturating_add(1));
+            }
+        }
+    }
+
+    pub fn select_path(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.path == path) {
+            self.selected = index;
+        }
+    }
+
+    pub fn move_selection(&mut self, up: bool) {
+        self.selected = if up {
+            self.selected.saturating_sub(1)
+        } else {
+            self.selected.saturating_add(1).min(self.entries.len().saturating_sub(1))
+        };
+    }
+
+    // expands a collapsed directory and collapses an expanded one
+    pub fn toggle(&mut self) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        if !entry.is_dir {
+            return;
+        }
+        let path = entry.path.clone();
+        if !self.expanded.remove(&path) {
+            self.expanded.insert(path);
+        }
+        self.refresh();
+    }
+
+    // collapses the selected directory, or selects the parent of the entry
+    pub fn collapse(&mut self) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        let path = entry.path.clone();
+        if entry.is_dir && self.expanded.remove(&path) {
+            self.refresh();
+        } else if let Some(parent) = path.parent() {
+            self.select_path(parent);
+        }
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        self.refresh();
+    }
+
+    // the directory new entries go into: the selected directory or the
+    // directory holding the selected file
+    pub fn target_dir(&self) -> PathBuf {
+        match self.selected_entry() {
+            Some(entry) if entry.is_dir => entry.path.clone(),
+            Some(entry) => entry.path.parent().map_or_else(|| self.root.clone(), Path::to_path_buf),
+            None => self.root.clone(),
+        }
+    }
+
+    // a trailing `/` in `name` creates a directory
+    pub fn create(&mut self, name: &str) -> Result<PathBuf, Error> {
+        let dir = self.target_dir();
+        let path = dir.join(name.trim_end_matches('/'));
+        if name.ends_with('/') {
+            fs::create_dir_all(&path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::OpenOptions::new().write(true).create_new(true).open(&path)?;
+        }
+        self.expanded.insert(dir);
+        self.refresh();
+        self.select_path(&path);
+        Ok(path)
+    }
+
+    pub fn rename(&mut self, from: &Path, name: &str) -> Result<PathBuf, Error> {
+        let to = from.parent().map_or_else(|| PathBuf::from(name), |parent| parent.join(name));
+        fs::rename(from, &to)?;
+        if self.expanded.remove(from) {
+            self.expanded.insert(to.clone());
+        }
+        self.refresh();
+        self.select_path(&to);
+        Ok(to)
+    }
+
+    pub fn delete(&mut self, path: &Path) -> Result<(), Error> {
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+        self.expanded.remove(path);
+        self.refresh();
+        Ok(())
+    }
+
+    // first visible line, scrolled so the selection fits into `height` lines
+    pub fn scroll(&mut self, height: usize) -> usize {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset.saturating_add(height) {
+            self.offset = self.selected.saturating_sub(height).saturating_add(1);
+        }
+        self.offset
+    }
+}
@@ -1,221 +1,443 @@
+use serde::Deserialize;
+use crate::highlighting;
+use crate::language::{self, Language};
 
+const AUTO_PAIRS: [&str; 5] = ["()", "[]", "{}", "\"\"", "''"];
+
+#[derive(Default)]
 pub struct FileType {
-    name: String,
-    hl_opts: HighlightingOptions,
+    language: Option<&'static Language>,
 }
 
-#[derive(Default)]
+#[derive(Default, Deserialize)]
+#[serde(default)]
 pub struct HighlightingOptions {
-    numbers: bool,
-    strings: bool,
+    line_comments: Vec<String>,
+    doc_comments: Vec<String>,
+    block_comments: Vec<BlockComment>,
+    strings: Vec<StringDelimiter>,
+    // letters that may precede any string delimiter, such as Python's `f"`
+    string_prefixes: Vec<String>,
+    // prefixes of `r#"..."#` style strings
+    raw_strings: Vec<String>,
     characters: bool,
-    comments: bool,
-    multiple_comments: bool,
-    primary_keywords: Vec<String>,
-    secondary_keywords: Vec<String>,
+    character_prefixes: Vec<String>,
+    lifetimes: bool,
+    // `#[` is closed by its matching `]`, other prefixes take a name
+    attributes: Vec<String>,
+    // `name!` invocations
+    macros: bool,
+    // `$name`, `${...}` and `$1` style expansions
+    variables: Vec<String>,
+    // `<<EOF` here-documents
+    heredocs: bool,
+    // a name or string followed by one of these is a key, `"a": 1` or `a = 1`
+    key_separators: Vec<char>,
+    // TOML `[table]` headers
+    table_headers: bool,
+    // Markdown `# heading` rows
+    headings: bool,
+    // YAML `&anchor` and `*alias`
+    anchors: Vec<String>,
+    // Markdown `*emphasis*` and `**strong**` delimiters
+    emphasis: Vec<String>,
+    numbers: Option<NumberRules>,
+    identifiers: Option<IdentifierRules>,
+    // `<` and `>` around type arguments are punctuation, not comparisons
+    generics: bool,
+    // characters highlighted one by one once nothing else matched
+    operators: String,
+    punctuation: String,
+    keywords: Keywords,
 }
 
-impl Default for FileType {
-    fn default() -> Self {
-        Self {
-            name: String::from("no filetype"),
-            hl_opts: HighlightingOptions::default(),
-        }
-    }
+#[derive(Deserialize)]
+pub struct BlockComment {
+    pub start: String,
+    pub end: String,
+    #[serde(default)]
+    pub nested: bool,
+    #[serde(default)]
+    pub doc: bool,
+}
+
+#[derive(Deserialize)]
+pub struct StringDelimiter {
+    pub start: String,
+    // defaults to the opening delimiter
+    end: Option<String>,
+    pub escape: Option<char>,
+    // whether the string may continue on the next row
+    #[serde(default)]
+    pub multiline: bool,
+    // prefix of expansions highlighted inside the string, `$` or `${`
+    pub interpolation: Option<String>,
+    #[serde(default)]
+    kind: StringKind,
+}
+
+// Markdown code spans and fenced blocks are lexed like strings
+#[derive(Default, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum StringKind {
+    #[default]
+    String,
+    Code,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct NumberRules {
+    // radix prefixes such as "0x", the digits after them may be hex
+    prefixes: Vec<String>,
+    separator: Option<char>,
+    exponent: bool,
+    suffixes: Vec<String>,
+}
+
+// how identifiers that are not keywords are classified
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct IdentifierRules {
+    // `name(` and the name after one of `definitions`
+    functions: bool,
+    definitions: Vec<String>,
+    // names starting with a capital letter
+    types: bool,
+    // ALL_CAPS names
+    constants: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Keywords {
+    primary: Vec<String>,
+    secondary: Vec<String>,
 }
 
+static NO_HIGHLIGHTING: HighlightingOptions = HighlightingOptions {
+    line_comments: Vec::new(),
+    doc_comments: Vec::new(),
+    block_comments: Vec::new(),
+    strings: Vec::new(),
+    string_prefixes: Vec::new(),
+    raw_strings: Vec::new(),
+    characters: false,
+    character_prefixes: Vec::new(),
+    lifetimes: false,
+    attributes: Vec::new(),
+    macros: false,
+    variables: Vec::new(),
+    heredocs: false,
+    key_separators: Vec::new(),
+    table_headers: false,
+    headings: false,
+    anchors: Vec::new(),
+    emphasis: Vec::new(),
+    numbers: None,
+    identifiers: None,
+    generics: false,
+    operators: String::new(),
+    punctuation: String::new(),
+    keywords: Keywords {
+        primary: Vec::new(),
+        secondary: Vec::new(),
+    },
+};
+
 impl FileType {
     pub fn name(&self) -> String {
-        self.name.clone()
-    }
-
-    pub fn from(filename: &str) -> Self {
-        let rust_primary_keywords = vec![
-            "as".to_string(),
-            "break".to_string(),
-            "const".to_string(),
-            "continue".to_string(),
-            "crate".to_string(),
-            "else".to_string(),
-            "enum".to_string(),
-            "extern".to_string(),
-            "false".to_string(),
-            "fn".to_string(),
-            "for".to_string(),
-            "if".to_string(),
-            "impl".to_string(),
-            "in".to_string(),
-            "let".to_string(),
-            "loop".to_string(),
-            "match".to_string(),
-            "mod".to_string(),
-            "move".to_string(),
-            "mut".to_string(),
-            "pub".to_string(),
-            "ref".to_string(),
-            "return".to_string(),
-            "self".to_string(),
-            "Self".to_string(),
-            "static".to_string(),
-            "struct".to_string(),
-            "super".to_string(),
-            "trait".to_string(),
-            "true".to_string(),
-            "type".to_string(),
-            "unsafe".to_string(),
-            "use".to_string(),
-            "where".to_string(),
-            "while".to_string(),
-            "dyn".to_string(),
-            "abstract".to_string(),
-            "become".to_string(),
-            "box".to_string(),
-            "do".to_string(),
-            "final".to_string(),
-            "macro".to_string(),
-            "override".to_string(),
-            "priv".to_string(),
-            "typeof".to_string(),
-            "unsized".to_string(),
-            "virtual".to_string(),
-            "yield".to_string(),
-            "async".to_string(),
-            "await".to_string(),
-            "try".to_string(),
-        ];
-
-        let rust_secondary_keywords = vec![
-            "bool".to_string(),
-            "char".to_string(),
-            "i8".to_string(),
-            "i16".to_string(),
-            "i32".to_string(),
-            "i64".to_string(),
-            "isize".to_string(),
-            "u8".to_string(),
-            "u16".to_string(),
-            "u32".to_string(),
-            "u64".to_string(),
-            "usize".to_string(),
-            "f32".to_string(),
-            "f64".to_string(),
-        ];
-
-        let java_primary_keywords = vec![
-            "void".to_string(),
-            "null".to_string(),
-            "true".to_string(),
-            "false".to_string(),
-            "enum".to_string(),
-            "public".to_string(),
-            "protected".to_string(),
-            "default".to_string(),
-            "private".to_string(),
-            "class".to_string(),
-            "interface".to_string(),
-            "abstract".to_string(),
-            "implement".to_string(),
-            "extends".to_string(),
-            "new".to_string(),
-            "import".to_string(),
-            "package".to_string(),
-            "if".to_string(),
-            "else".to_string(),
-            "while".to_string(),
-            "for".to_string(),
-            "switch".to_string(),
-            "case".to_string(),
-            "default".to_string(),
-            "do".to_string(),
-            "break".to_string(),
-            "continue".to_string(),
-            "return".to_string(),
-            "instanceof".to_string(),
-            "static".to_string(),
-            "final".to_string(),
-            "super".to_string(),
-            "this".to_string(),
-            "native".to_string(),
-            "synchronized".to_string(),
-            "volatile".to_string(),
-            "const".to_string(),
-        ];
-
-        let java_secondary_keywords = vec![
-            "byte".to_string(),
-            "boolean".to_string(),
-            "char".to_string(),
-            "short".to_string(),
-            "int".to_string(),
-            "float".to_string(),
-            "long".to_string(),
-            "double".to_string(),
-            "Object".to_string(),
-        ];
-
-
-        if filename.ends_with(".rs") {
-            return Self {
-                name: String::from("Rust"),
-                hl_opts: HighlightingOptions {
-                    numbers: true,
-                    strings: true,
-                    characters: true,
-                    comments: true,
-                    multiple_comments: true,
-                    primary_keywords: rust_primary_keywords,
-                    secondary_keywords: rust_secondary_keywords,
-                },
-            };
-        }
-        if filename.ends_with(".java") {
-            return Self {
-                name: String::from("Java"),
-                hl_opts: HighlightingOptions {
-                    numbers: true,
-                    strings: true,
-                    characters: true,
-                    comments: true,
-                    multiple_comments: true,
-                    primary_keywords: java_primary_keywords,
-                    secondary_keywords: java_secondary_keywords,
-                }
-            }
+        self.language.map_or_else(|| String::from("no filetype"), |language| language.name().to_string())
+    }
+
+    // `lines` are the first and last few rows of the file, a modeline in
+    // them beats the file name, which beats the `#!` line and the content
+    pub fn detect(filename: Option<&str>, lines: &[&str]) -> Self {
+        let languages = language::languages();
+        let language = lines.iter().find_map(|line| modeline(line)).and_then(|name| languages.by_name(name))
+            .or_else(|| filename.and_then(|filename| languages.find(filename)))
+            .or_else(|| lines.first().and_then(|line| shebang(line)).and_then(|program| languages.by_interpreter(program)))
+            .or_else(|| lines.iter().find(|line| !line.trim().is_empty()).and_then(|line| languages.sniff(line)));
+        Self { language }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        language::languages().by_name(name.trim()).map(|language| Self { language: Some(language) })
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    pub fn grammar(&self) -> Option<&'static str> {
+        self.language.and_then(Language::grammar)
+    }
+
+    pub fn indent(&self) -> &str {
+        self.language.and_then(Language::indent).unwrap_or("    ")
+    }
+
+    pub fn auto_pairs(&self) -> Vec<(char, char)> {
+        let pair = |pair: &str| {
+            let mut chars = pair.chars();
+            chars.next().zip(chars.next())
+        };
+        match self.language.and_then(Language::auto_pairs) {
+            Some(pairs) => pairs.iter().filter_map(|p| pair(p)).collect(),
+            None => AUTO_PAIRS.iter().filter_map(|p| pair(p)).collect(),
         }
-        Self::default()
     }
 
     pub fn highlighting_options(&self) -> &HighlightingOptions {
-        &self.hl_opts
+        self.language.map_or(&NO_HIGHLIGHTING, Language::highlighting_options)
     }
 }
 
 impl HighlightingOptions {
-    pub fn numbers(&self) -> bool {
-        self.numbers
+    pub fn numbers(&self) -> Option<&NumberRules> {
+        self.numbers.as_ref()
+    }
+
+    pub fn identifiers(&self) -> Option<&IdentifierRules> {
+        self.identifiers.as_ref()
     }
 
-    pub fn strings(&self) -> bool {
-        self.strings
+    pub fn generics(&self) -> bool {
+        self.generics
+    }
+
+    pub fn operators(&self) -> &str {
+        &self.operators
+    }
+
+    pub fn punctuation(&self) -> &str {
+        &self.punctuation
+    }
+
+    pub fn strings(&self) -> &[StringDelimiter] {
+        &self.strings
+    }
+
+    pub fn string_prefixes(&self) -> &[String] {
+        &self.string_prefixes
+    }
+
+    pub fn raw_strings(&self) -> &[String] {
+        &self.raw_strings
     }
 
     pub fn characters(&self) -> bool {
         self.characters
     }
 
-    pub fn comments(&self) -> bool {
-        self.comments
+    pub fn character_prefixes(&self) -> &[String] {
+        &self.character_prefixes
+    }
+
+    pub fn lifetimes(&self) -> bool {
+        self.lifetimes
+    }
+
+    pub fn attributes(&self) -> &[String] {
+        &self.attributes
+    }
+
+    pub fn macros(&self) -> bool {
+        self.macros
+    }
+
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    pub fn heredocs(&self) -> bool {
+        self.heredocs
+    }
+
+    pub fn key_separators(&self) -> &[char] {
+        &self.key_separators
+    }
+
+    pub fn table_headers(&self) -> bool {
+        self.table_headers
+    }
+
+    pub fn headings(&self) -> bool {
+        self.headings
+    }
+
+    pub fn anchors(&self) -> &[String] {
+        &self.anchors
+    }
+
+    pub fn emphasis(&self) -> &[String] {
+        &self.emphasis
+    }
+
+    pub fn line_comments(&self) -> &[String] {
+        &self.line_comments
+    }
+
+    pub fn doc_comments(&self) -> &[String] {
+        &self.doc_comments
+    }
+
+    pub fn block_comments(&self) -> &[BlockComment] {
+        &self.block_comments
     }
 
     pub fn primary_keywords(&self) -> &Vec<String> {
-        &self.primary_keywords
+        &self.keywords.primary
     }
 
     pub fn secondary_keywords(&self) -> &Vec<String> {
-        &self.secondary_keywords
+        &self.keywords.secondary
     }
+}
+
+impl StringDelimiter {
+    pub fn end(&self) -> &str {
+        self.end.as_deref().unwrap_or(&self.start)
+    }
+
+    pub fn hl_type(&self) -> highlighting::Type {
+        match self.kind {
+            StringKind::String => highlighting::Type::String,
+            StringKind::Code => highlighting::Type::Code,
+        }
+    }
+}
 
-    pub fn multiple_comments(&self) -> bool {
-        self.multiple_comments
+impl IdentifierRules {
+    pub fn functions(&self) -> bool {
+        self.functions
     }
-}
\ No newline at end of file
+
+    pub fn definitions(&self) -> &[String] {
+        &self.definitions
+    }
+
+    pub fn types(&self) -> bool {
+        self.types
+    }
+
+    pub fn constants(&self) -> bool {
+        self.constants
+    }
+}
+
+impl NumberRules {
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    pub fn separator(&self) -> Option<char> {
+        self.separator
+    }
+
+    pub fn exponent(&self) -> bool {
+        self.exponent
+    }
+
+    pub fn suffixes(&self) -> &[String] {
+        &self.suffixes
+    }
+}
+
+// `vim: set ft=python :`, `vi:ft=sh` or `-*- mode: python -*-`
+fn modeline(line: &str) -> Option<&str> {
+    if let Some((_, rest)) = line.split_once("-*-") {
+        let (variables, _) = rest.split_once("-*-")?;
+        if !variables.contains(':') {
+            return Some(variables.trim());
+        }
+        return variables.split(';').find_map(|variable| {
+            let (key, value) = variable.split_once(':')?;
+            key.trim().eq_ignore_ascii_case("mode").then(|| value.trim())
+        });
+    }
+    ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let start = line.find(marker)?;
+        // `navi:` is not a modeline
+        if !line[..start].chars().next_back().is_none_or(char::is_whitespace) {
+            return None;
+        }
+        line[start.saturating_add(marker.len())..]
+            .split(|c: char| c == ':' || c.is_whitespace())
+            .find_map(|option| {
+                let (key, value) = option.split_once('=')?;
+                matches!(key, "ft" | "filetype" | "syn" | "syntax").then_some(value)
+            })
+    })
+}
+
+// `#!/usr/bin/env -S python3 -u` runs `python3`
+fn shebang(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        return words.find(|word| !word.starts_with('-') && !word.contains('='));
+    }
+    Some(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{modeline, shebang, FileType};
+
+    fn detected(filename: Option<&str>, lines: &[&str]) -> String {
+        FileType::detect(filename, lines).name()
+    }
+
+    #[test]
+    fn vim_modelines() {
+        assert_eq!(modeline("# vim: set ft=python :"), Some("python"));
+        assert_eq!(modeline("/* vi:ts=4:filetype=c */"), Some("c"));
+        assert_eq!(modeline("  ex: set tw=80 syntax=yaml:"), Some("yaml"));
+        assert_eq!(modeline("# vim: set ts=4 :"), None);
+        // only a whole word starts one
+        assert_eq!(modeline("navi: ft=sh"), None);
+    }
+
+    #[test]
+    fn emacs_modelines() {
+        assert_eq!(modeline("# -*- python -*-"), Some("python"));
+        assert_eq!(modeline(";; -*- coding: utf-8; mode: sh -*-"), Some("sh"));
+        assert_eq!(modeline("# -*- coding: utf-8 -*-"), None);
+        assert_eq!(modeline("# -*- unclosed"), None);
+    }
+
+    #[test]
+    fn shebang_programs() {
+        assert_eq!(shebang("#!/bin/sh"), Some("sh"));
+        assert_eq!(shebang("#! /usr/bin/python3.11 -u"), Some("python3.11"));
+        assert_eq!(shebang("#!/usr/bin/env node"), Some("node"));
+        assert_eq!(shebang("#!/usr/bin/env -S python3 -u"), Some("python3"));
+        assert_eq!(shebang("#!/usr/bin/env -i PATH=/bin bash"), Some("bash"));
+        assert_eq!(shebang("# comment"), None);
+        assert_eq!(shebang("#!"), None);
+    }
+
+    #[test]
+    fn modeline_beats_the_file_name() {
+        assert_eq!(detected(Some("build.rs"), &["// vim: set ft=python :"]), "Python");
+        // in the last rows too
+        assert_eq!(detected(Some("notes.txt"), &["text", "# -*- mode: yaml -*-"]), "YAML");
+    }
+
+    #[test]
+    fn file_name_beats_the_shebang() {
+        assert_eq!(detected(Some("tool.py"), &["#!/bin/sh"]), "Python");
+        assert_eq!(detected(Some("src/Makefile"), &["#!/bin/sh"]), "Make");
+    }
+
+    #[test]
+    fn shebang_beats_the_content() {
+        assert_eq!(detected(Some("run"), &["#!/usr/bin/env python3.11", "---"]), "Python");
+        assert_eq!(detected(None, &["#!/bin/bash"]), "Shell");
+    }
+
+    #[test]
+    fn content_is_sniffed_last() {
+        assert_eq!(detected(Some("config"), &["", "---", "a: 1"]), "YAML");
+        assert_eq!(detected(None, &["{\"a\": 1}"]), "JSON");
+        assert_eq!(detected(Some("notes"), &["hello"]), "no filetype");
+    }
+}
@@ -0,0 +1,202 @@
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use ignore::WalkBuilder;
+
+// how many of the best matches are kept for display
+const MAX_MATCHES: usize = 200;
+const MATCH_SCORE: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 24;
+const BOUNDARY_BONUS: i64 = 20;
+const FILENAME_BONUS: i64 = 8;
+const GAP_PENALTY: i64 = 1;
+
+pub struct Match {
+    pub path: String,
+    // char indices of `path` the query matched
+    pub positions: Vec<usize>,
+    score: i64,
+}
+
+// the project's files, collected by a background walk, ranked against a query
+pub struct Finder {
+    files: Vec<String>,
+    receiver: Receiver<String>,
+    walking: bool,
+    query: String,
+    matches: Vec<Match>,
+    selected: usize,
+}
+
+impl Finder {
+    pub fn start(root: &Path) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let files = project_files(root);
+        thread::spawn(move || {
+            for path in files {
+                // the finder is gone
+                if sender.send(path).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            files: Vec::new(),
+            receiver,
+            walking: true,
+            query: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    // takes what the walk found since the last call, re-ranking when it is new
+    pub fn poll(&mut self) {
+        let count = self.files.len();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(path) => self.files.push(path),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.walking = false;
+                    break;
+                },
+            }
+        }
+        if self.files.len() != count {
+            self.rank();
+        }
+    }
+
+    pub fn set_query(&mut self, query: &str) {
+        if query != self.query {
+            query.clone_into(&mut self.query);
+            self.selected = 0;
+            self.rank();
+        }
+    }
+
+    fn rank(&mut self) {
+        let mut matches: Vec<Match> = self.files.iter()
+            .filter_map(|path| score(&self.query, path).map(|(score, positions)| Match {
+                path: path.clone(),
+                positions,
+                score,
+            }))
+            .collect();
+        matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.len().cmp(&b.path.len())).then_with(|| a.path.cmp(&b.path)));
+        matches.truncate(MAX_MATCHES);
+        self.matches = matches;
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+
+    pub fn move_selection(&mut self, up: bool) {
+        self.selected = if up {
+            self.selected.saturating_sub(1)
+        } else {
+            self.selected.saturating_add(1).min(self.matches.len().saturating_sub(1))
+        };
+    }
+
+    pub fn matches(&self) -> &[Match] {
+        &self.matches
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_match(&self) -> Option<&Match> {
+        self.matches.get(self.selected)
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_walking(&self) -> bool {
+        self.walking
+    }
+}
+
+// paths of the project's files, leaving out what .gitignore lists and
+// target/, walked lazily
+pub fn project_files(root: &Path) -> impl Iterator<Item = String> {
+    WalkBuilder::new(root)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != "target")
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+        .map(|entry| {
+            let path = entry.path();
+            path.strip_prefix(".").unwrap_or(path).display().to_string()
+        })
+}
+
+// scores `candidate` when every char of `query` appears in it in order,
+// ignoring case unless the query has an uppercase letter; matches at word
+// starts, in a row and in the file name score higher, gaps lower
+pub fn score(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| if case_sensitive { c } else { c.to_ascii_lowercase() };
+    let query: Vec<char> = query.chars().map(fold).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let Some(first) = query.first() else {
+        return Some((0, Vec::new()));
+    };
+    let filename_start = chars.iter().rposition(|c| *c == '/').map_or(0, |index| index.saturating_add(1));
+    // a greedy match from every place the first char occurs, keeping the best
+    chars.iter()
+        .enumerate()
+        .filter(|(_, c)| fold(**c) == *first)
+        .filter_map(|(start, _)| {
+            let mut positions = vec![start];
+            let mut index = start;
+            for c in query.iter().skip(1) {
+                index = chars.iter()
+                    .enumerate()
+                    .skip(index.saturating_add(1))
+                    .find(|(_, candidate)| fold(**candidate) == *c)?
+                    .0;
+                positions.push(index);
+            }
+            Some(positions)
+        })
+        .map(|positions| (positions_score(&chars, &positions, filename_start), positions))
+        .max_by_key(|(score, _)| *score)
+}
+
+fn positions_score(chars: &[char], positions: &[usize], filename_start: usize) -> i64 {
+    let mut score = 0_i64;
+    let mut previous: Option<usize> = None;
+    for &index in positions {
+        score = score.saturating_add(MATCH_SCORE);
+        match previous {
+            Some(previous) if previous.saturating_add(1) == index => score = score.saturating_add(CONSECUTIVE_BONUS),
+            Some(previous) => {
+                let gap = i64::try_from(index.saturating_sub(previous)).unwrap_or(i64::MAX);
+                score = score.saturating_sub(gap.saturating_mul(GAP_PENALTY));
+            },
+            None => (),
+        }
+        if is_boundary(chars, index) {
+            score = score.saturating_add(BOUNDARY_BONUS);
+        }
+        if index >= filename_start {
+            score = score.saturating_add(FILENAME_BONUS);
+        }
+        previous = Some(index);
+    }
+    score
+}
+
+// the start of the path, of a path component, of a `snake_case`,
+// `kebab-case` or `dotted` part or of a `camelCase` hump
+fn is_boundary(chars: &[char], index: usize) -> bool {
+    let Some(previous) = index.checked_sub(1).and_then(|previous| chars.get(previous)) else {
+        return true;
+    };
+    let current = chars.get(index).copied().unwrap_or_default();
+    matches!(previous, '/' | '_' | '-' | '.' | ' ') || (previous.is_lowercase() && current.is_uppercase())
+}
@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::Position;
+use crate::finder;
+
+pub struct Hit {
+    pub path: String,
+    // where the match starts, in graphemes
+    pub position: Position,
+    pub len: usize,
+    // the match in `line`, in bytes
+    pub bytes: Range<usize>,
+    pub line: String,
+}
+
+// a line of the results list: a file heading its hits, or one hit
+pub enum Line<'a> {
+    File(&'a str, usize),
+    Hit(usize),
+}
+
+// a project search running in a background thread, with the hits so far
+pub struct Grep {
+    receiver: Receiver<Hit>,
+    searching: bool,
+    hits: Vec<Hit>,
+    selected: usize,
+}
+
+impl Grep {
+    // files open in a buffer are searched as `open` holds them, under
+    // their canonical paths, so hits on unsaved lines are where the buffer
+    // has them; every other file as it is on disk
+    pub fn start(root: &Path, pattern: Regex, open: Vec<(PathBuf, String)>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let files = finder::project_files(root);
+        thread::spawn(move || {
+            for path in files {
+                let buffer = if open.is_empty() {
+                    None
+                } else {
+                    fs::canonicalize(&path).ok().and_then(|canonical| open.iter().find(|(open, _)| *open == canonical))
+                };
+                let contents = match buffer {
+                    Some((_, text)) => Cow::Borrowed(text.as_str()),
+                    None => match fs::read_to_string(&path) {
+                        Ok(contents) => Cow::Owned(contents),
+                        // binary and unreadable files are skipped
+                        Err(_) => continue,
+                    },
+                };
+                for hit in search(&pattern, &path, &contents) {
+                    // the search was closed
+                    if sender.send(hit).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Self {
+            receiver,
+            searching: true,
+            hits: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(hit) => self.hits.push(hit),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.searching = false;
+                    break;
+                },
+            }
+        }
+    }
+
+    // collects hits for up to `timeout`, less when the search ends first
+    pub fn wait(&mut self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while self.searching {
+            match self.receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(hit) => self.hits.push(hit),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => self.searching = false,
+            }
+        }
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.searching
+    }
+
+    pub fn hits(&self) -> &[Hit] {
+        &self.hits
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn into_hits(self) -> Vec<Hit> {
+        self.hits
+    }
+
+    pub fn selected_hit(&self) -> Option<&Hit> {
+        self.hits.get(self.selected)
+    }
+
+    pub fn move_selection(&mut self, up: bool, count: usize) {
+        self.selected = if up {
+            self.selected.saturating_sub(count)
+        } else {
+            self.selected.saturating_add(count).min(self.hits.len().saturating_sub(1))
+        };
+    }
+
+    // the hits grouped under the file they are in, the files in walk order
+    pub fn lines(&self) -> Vec<Line<'_>> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(first) = self.hits.get(start) {
+            let count = self.hits.iter().skip(start).take_while(|hit| hit.path == first.path).count();
+            lines.push(Line::File(&first.path, count));
+            lines.extend((start..start.saturating_add(count)).map(Line::Hit));
+            start = start.saturating_add(count);
+        }
+        lines
+    }
+}
+
+// every match of `pattern` in the lines of `contents`
+pub fn search(pattern: &Regex, path: &str, contents: &str) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    for (y, line) in contents.lines().enumerate() {
+        for found in pattern.find_iter(line).filter(|found| !found.is_empty()) {
+            let before = line.get(..found.start()).unwrap_or_default();
+            hits.push(Hit {
+                path: path.to_string(),
+                position: Position { x: before.graphemes(true).count(), y },
+                len: found.as_str().graphemes(true).count(),
+                bytes: found.range(),
+                line: line.to_string(),
+            });
+        }
+    }
+    hits
+}
+
+// `/regex/` is searched as written, anything else literally
+pub fn pattern(query: &str) -> Result<Regex, regex::Error> {
+    match expression(query) {
+        Some(expression) => Regex::new(expression),
+        None => Regex::new(&regex::escape(query)),
+    }
+}
+
+pub fn is_regex(query: &str) -> bool {
+    expression(query).is_some()
+}
+
+fn expression(query: &str) -> Option<&str> {
+    query.strip_prefix('/')
+        .and_then(|query| query.strip_suffix('/'))
+        .filter(|expression| !expression.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+    use super::{pattern, Grep};
+
+    #[test]
+    fn open_files_are_searched_as_their_buffers_hold_them() {
+        let root = std::env::temp_dir().join(format!("heitx-grep-{}", std::process::id()));
+        fs::create_dir_all(&root).expect("temporary directory");
+        fs::write(root.join("open.txt"), "foo\n").expect("file written");
+        fs::write(root.join("closed.txt"), "x\nfoo\n").expect("file written");
+        let open = vec![(fs::canonicalize(root.join("open.txt")).expect("file exists"), "unsaved\nlines\nfoo foo\n".to_string())];
+        let mut grep = Grep::start(&root, pattern("foo").expect("pattern"), open);
+        while grep.is_searching() {
+            grep.wait(Duration::from_millis(10));
+        }
+        let mut hits: Vec<(String, usize, usize)> = grep.hits().iter()
+            .map(|hit| (hit.path.rsplit('/').next().unwrap_or_default().to_string(), hit.position.y, hit.position.x))
+            .collect();
+        hits.sort();
+        fs::remove_dir_all(&root).expect("temporary directory removed");
+        assert_eq!(hits, [("closed.txt".to_string(), 1, 0), ("open.txt".to_string(), 2, 0), ("open.txt".to_string(), 2, 4)]);
+    }
+}
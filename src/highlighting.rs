@@ -1,6 +1,6 @@
 use termion::color;
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Type {
     None,
     Number,
@@ -11,6 +11,39 @@ pub enum Type {
     MultipleComments,
     PrimaryKeywords,
     SecondaryKeywords,
+    DocComment,
+    Lifetime,
+    Attribute,
+    Macro,
+    Variable,
+    Key,
+    Heading,
+    Anchor,
+    Emphasis,
+    Code,
+    Function,
+    Class,
+    Constant,
+    Operator,
+    Punctuation,
+    Escape,
+    MatchingBracket,
+    MismatchedBracket,
+}
+
+// lexer state carried from the end of one row into the next
+#[derive(PartialEq, Clone, Default, Debug)]
+pub enum State {
+    #[default]
+    Normal,
+    // index of the block comment pair and its nesting depth
+    BlockComment(usize, usize),
+    // index of the string delimiter
+    String(usize),
+    // number of `#` closing the raw string
+    RawString(usize),
+    // line that ends the here-document
+    Heredoc(String),
 }
 
 impl Type {
@@ -24,7 +57,31 @@ impl Type {
             Type::PrimaryKeywords => color::Rgb(181, 137, 0),
             Type::SecondaryKeywords => color::Rgb(42, 161, 152),
             Type::MultipleComments => color::Rgb(154, 255, 154),
+            Type::DocComment => color::Rgb(95, 175, 95),
+            Type::Lifetime => color::Rgb(203, 75, 22),
+            Type::Attribute => color::Rgb(147, 161, 161),
+            Type::Macro => color::Rgb(38, 139, 210),
+            Type::Variable => color::Rgb(133, 153, 0),
+            Type::Key => color::Rgb(38, 139, 210),
+            Type::Heading => color::Rgb(203, 75, 22),
+            Type::Anchor => color::Rgb(108, 113, 196),
+            Type::Emphasis => color::Rgb(238, 232, 213),
+            Type::Code => color::Rgb(42, 161, 152),
+            Type::Function => color::Rgb(97, 175, 239),
+            Type::Class => color::Rgb(229, 192, 123),
+            Type::Constant => color::Rgb(198, 120, 221),
+            Type::Operator => color::Rgb(86, 182, 194),
+            Type::Punctuation => color::Rgb(171, 178, 191),
+            Type::Escape => color::Rgb(209, 154, 102),
+            Type::MatchingBracket => color::Rgb(255, 215, 0),
+            Type::MismatchedBracket => color::Rgb(255, 0, 95),
             _ => color::Rgb(255, 255, 255),
         }
     }
-}
\ No newline at end of file
+
+    pub fn is_comment_or_string(self) -> bool {
+        matches!(self,
+            Type::String | Type::Character | Type::Escape | Type::Code
+            | Type::Comment | Type::MultipleComments | Type::DocComment)
+    }
+}
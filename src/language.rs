@@ -0,0 +1,246 @@
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use serde::Deserialize;
+use crate::config;
+use crate::HighlightingOptions;
+
+// compiled in, user definitions with the same name replace them
+const BUILTIN: [(&str, &str); 15] = [
+    ("rust.toml", include_str!("../languages/rust.toml")),
+    ("java.toml", include_str!("../languages/java.toml")),
+    ("c.toml", include_str!("../languages/c.toml")),
+    ("cpp.toml", include_str!("../languages/cpp.toml")),
+    ("python.toml", include_str!("../languages/python.toml")),
+    ("go.toml", include_str!("../languages/go.toml")),
+    ("javascript.toml", include_str!("../languages/javascript.toml")),
+    ("typescript.toml", include_str!("../languages/typescript.toml")),
+    ("shell.toml", include_str!("../languages/shell.toml")),
+    ("json.toml", include_str!("../languages/json.toml")),
+    ("toml.toml", include_str!("../languages/toml.toml")),
+    ("yaml.toml", include_str!("../languages/yaml.toml")),
+    ("markdown.toml", include_str!("../languages/markdown.toml")),
+    ("make.toml", include_str!("../languages/make.toml")),
+    ("dockerfile.toml", include_str!("../languages/dockerfile.toml")),
+];
+
+static LANGUAGES: OnceLock<Languages> = OnceLock::new();
+
+#[derive(Deserialize)]
+pub struct Language {
+    name: String,
+    // tree-sitter grammar, when heitx is built with one for this language
+    #[cfg_attr(not(feature = "tree-sitter"), allow(dead_code))]
+    grammar: Option<String>,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    globs: Vec<String>,
+    // exact file names such as `Makefile`
+    #[serde(default)]
+    filenames: Vec<String>,
+    // other names used by modelines and `set filetype`, `py` or `sh`
+    #[serde(default)]
+    aliases: Vec<String>,
+    // programs named by a `#!` line, version suffixes are ignored
+    #[serde(default)]
+    interpreters: Vec<String>,
+    // prefixes of the first non-blank row that give the language away
+    #[serde(default)]
+    first_lines: Vec<String>,
+    // one level of indentation, four spaces when not given
+    indent: Option<String>,
+    // opening and closing character typed together, such as "()"
+    auto_pairs: Option<Vec<String>>,
+    #[serde(flatten)]
+    hl_opts: HighlightingOptions,
+}
+
+pub struct Languages {
+    languages: Vec<Language>,
+    errors: Vec<String>,
+}
+
+impl Language {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn highlighting_options(&self) -> &HighlightingOptions {
+        &self.hl_opts
+    }
+
+    pub fn indent(&self) -> Option<&str> {
+        self.indent.as_deref()
+    }
+
+    pub fn auto_pairs(&self) -> Option<&[String]> {
+        self.auto_pairs.as_deref()
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    pub fn grammar(&self) -> Option<&str> {
+        self.grammar.as_deref()
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+
+    fn matches(&self, filename: &str) -> bool {
+        let path = Path::new(filename);
+        if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+            if self.extensions.iter().any(|ext| ext == extension) {
+                return true;
+            }
+        }
+        let basename = path.file_name().and_then(|name| name.to_str()).unwrap_or(filename);
+        self.globs.iter().any(|glob| glob_match(glob, basename) || glob_match(glob, filename))
+    }
+}
+
+impl Languages {
+    fn load() -> Self {
+        let mut languages = Vec::new();
+        let mut errors = Vec::new();
+        for (file, source) in BUILTIN {
+            match toml::from_str::<Language>(source) {
+                Ok(language) => languages.push(language),
+                Err(e) => errors.push(format!("{file}: {}", e.message())),
+            }
+        }
+        if let Some(dir) = config::config_dir() {
+            Self::load_dir(&dir.join("languages"), &mut languages, &mut errors);
+        }
+        Self { languages, errors }
+    }
+
+    fn load_dir(dir: &Path, languages: &mut Vec<Language>, errors: &mut Vec<String>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let language = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| toml::from_str::<Language>(&source).map_err(|e| e.message().to_string()));
+            match language {
+                Ok(language) => {
+                    // user definitions are searched first
+                    languages.retain(|l| !l.name.eq_ignore_ascii_case(&language.name));
+                    languages.insert(0, language);
+                },
+                Err(e) => errors.push(format!("{}: {e}", path.display())),
+            }
+        }
+    }
+
+    // exact file names win over extensions and globs
+    pub fn find(&self, filename: &str) -> Option<&Language> {
+        let basename = Path::new(filename).file_name().and_then(|name| name.to_str()).unwrap_or(filename);
+        self.languages.iter()
+            .find(|language| language.filenames.iter().any(|name| name == basename))
+            .or_else(|| self.languages.iter().find(|language| language.matches(filename)))
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Language> {
+        self.languages.iter().find(|language| language.is_named(name))
+    }
+
+    // `python3.12` is looked up as `python3.12` and then `python`
+    pub fn by_interpreter(&self, program: &str) -> Option<&Language> {
+        let unversioned = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        self.languages.iter().find(|language| language.interpreters.iter().any(|name| name == program))
+            .or_else(|| self.languages.iter().find(|language| language.interpreters.iter().any(|name| name == unversioned)))
+    }
+
+    pub fn sniff(&self, first_line: &str) -> Option<&Language> {
+        self.languages.iter().find(|language| language.first_lines.iter().any(|prefix| first_line.starts_with(prefix.as_str())))
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+}
+
+pub fn languages() -> &'static Languages {
+    LANGUAGES.get_or_init(Languages::load)
+}
+
+// `*` matches any run of characters, `?` exactly one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            },
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            _ => {
+                if let Some((star, matched)) = backtrack {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, t));
+                } else {
+                    return false;
+                }
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, languages};
+
+    fn interpreted_by(program: &str) -> Option<&'static str> {
+        languages().by_interpreter(program).map(|language| language.name())
+    }
+
+    fn found(filename: &str) -> Option<&'static str> {
+        languages().find(filename).map(|language| language.name())
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob_match("Dockerfile.*", "Dockerfile.dev"));
+        assert!(!glob_match("Dockerfile.*", "Dockerfile"));
+        assert!(glob_match("*.Dockerfile", "api.Dockerfile"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(glob_match("*", ""));
+        // a star gives back what it took when the rest does not match
+        assert!(glob_match("*a*b", "xaxxab"));
+        assert!(!glob_match("*a*b", "xbxa"));
+    }
+
+    #[test]
+    fn versioned_interpreters() {
+        assert_eq!(interpreted_by("python3.11"), Some("Python"));
+        assert_eq!(interpreted_by("python3"), Some("Python"));
+        assert_eq!(interpreted_by("bash5"), Some("Shell"));
+        assert_eq!(interpreted_by("nodejs"), Some("JavaScript"));
+        assert_eq!(interpreted_by("perl"), None);
+    }
+
+    #[test]
+    fn file_names_beat_extensions_and_globs() {
+        assert_eq!(found("Cargo.lock"), Some("TOML"));
+        assert_eq!(found("docker/Dockerfile.prod"), Some("Dockerfile"));
+        assert_eq!(found("./src/main.rs"), Some("Rust"));
+        assert_eq!(found("README"), None);
+    }
+}
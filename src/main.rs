@@ -7,6 +7,17 @@ mod document;
 mod row;
 mod highlighting;
 mod filetype;
+mod language;
+mod config;
+mod explorer;
+mod finder;
+mod grep;
+mod replace;
+mod command;
+mod action;
+mod window;
+#[cfg(feature = "tree-sitter")]
+mod syntax;
 
 use editor::Editor;
 pub use terminal::Terminal;
@@ -0,0 +1,339 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use regex::Regex;
+use crate::grep::Hit;
+
+pub struct Change {
+    pub hit: Hit,
+    pub replacement: String,
+    pub included: bool,
+}
+
+impl Change {
+    // the hit's line with only this change made
+    pub fn line_after(&self) -> String {
+        apply(&self.hit.line, &[self])
+    }
+}
+
+// a line of the preview: a file heading its changes, or one side of a
+// change's diff
+pub enum Line<'a> {
+    File(&'a str, usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+// the matches of a project search with what replaces them, each of which
+// can be left out before the changes are made
+pub struct Replace {
+    changes: Vec<Change>,
+    selected: usize,
+}
+
+impl Replace {
+    // with `expand`, `$1` and `${name}` in `replacement` stand for groups
+    // of `pattern`
+    pub fn new(pattern: &Regex, replacement: &str, expand: bool, hits: Vec<Hit>) -> Self {
+        let changes = hits.into_iter().map(|hit| {
+            let mut text = String::new();
+            match pattern.captures_at(&hit.line, hit.bytes.start) {
+                Some(captures) if expand => captures.expand(replacement, &mut text),
+                _ => text.push_str(replacement),
+            }
+            Change { hit, replacement: text, included: true }
+        }).collect();
+        Self { changes, selected: 0 }
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn move_selection(&mut self, up: bool, count: usize) {
+        self.selected = if up {
+            self.selected.saturating_sub(count)
+        } else {
+            self.selected.saturating_add(count).min(self.changes.len().saturating_sub(1))
+        };
+    }
+
+    pub fn toggle(&mut self) {
+        if let Some(change) = self.changes.get_mut(self.selected) {
+            change.included = !change.included;
+        }
+    }
+
+    // includes every change of the selected file, or leaves them all out
+    // when they all are included already
+    pub fn toggle_file(&mut self) {
+        let Some(path) = self.changes.get(self.selected).map(|change| change.hit.path.clone()) else {
+            return;
+        };
+        let include = !self.changes.iter().filter(|change| change.hit.path == path).all(|change| change.included);
+        for change in self.changes.iter_mut().filter(|change| change.hit.path == path) {
+            change.included = include;
+        }
+    }
+
+    // the included changes grouped by file
+    pub fn files(&self) -> Vec<(&str, Vec<&Change>)> {
+        let mut files: Vec<(&str, Vec<&Change>)> = Vec::new();
+        for change in self.changes.iter().filter(|change| change.included) {
+            match files.last_mut() {
+                Some((path, changes)) if *path == change.hit.path => changes.push(change),
+                _ => files.push((&change.hit.path, vec![change])),
+            }
+        }
+        files
+    }
+
+    pub fn lines(&self) -> Vec<Line<'_>> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(first) = self.changes.get(start) {
+            let changes: Vec<&Change> = self.changes.iter().skip(start).take_while(|change| change.hit.path == first.hit.path).collect();
+            let included = changes.iter().filter(|change| change.included).count();
+            lines.push(Line::File(&first.hit.path, included, changes.len()));
+            for index in start..start.saturating_add(changes.len()) {
+                lines.push(Line::Removed(index));
+                lines.push(Line::Added(index));
+            }
+            start = start.saturating_add(changes.len());
+        }
+        lines
+    }
+}
+
+// `line` with `changes` made, the changes being hits on it
+pub fn apply(line: &str, changes: &[&Change]) -> String {
+    let mut changes = changes.to_vec();
+    changes.sort_by_key(|change| std::cmp::Reverse(change.hit.bytes.start));
+    let mut line = line.to_string();
+    for change in changes {
+        if line.get(change.hit.bytes.clone()).is_some() {
+            line.replace_range(change.hit.bytes.clone(), &change.replacement);
+        }
+    }
+    line
+}
+
+// `contents` with `changes` made, None when a line changed since the search
+pub fn rewrite(contents: &str, changes: &[&Change]) -> Option<String> {
+    let mut rewritten = String::with_capacity(contents.len());
+    let mut made = 0_usize;
+    for (y, line) in contents.split_inclusive('\n').enumerate() {
+        let on_line: Vec<&Change> = changes.iter().copied().filter(|change| change.hit.position.y == y).collect();
+        made = made.saturating_add(on_line.len());
+        if on_line.is_empty() {
+            rewritten.push_str(line);
+            continue;
+        }
+        let body = line.strip_suffix('\n').map_or(line, |body| body.strip_suffix('\r').unwrap_or(body));
+        if on_line.iter().any(|change| change.hit.line != body) {
+            return None;
+        }
+        rewritten.push_str(&apply(body, &on_line));
+        rewritten.push_str(line.get(body.len()..).unwrap_or_default());
+    }
+    // the file lost the lines of the others
+    (made == changes.len()).then_some(rewritten)
+}
+
+// writes every file next to itself first, with a link to each original
+// kept aside, and only renames the copies over the originals once all of
+// them are written; when a rename fails, the files already replaced get
+// their originals back
+pub fn write_all(files: &[(String, String)]) -> Result<(), Error> {
+    write_all_with(files, |from, to| fs::rename(from, to))
+}
+
+// `write_all` with the renames made by `rename`
+fn write_all_with(files: &[(String, String)], rename: impl Fn(&str, &str) -> Result<(), Error>) -> Result<(), Error> {
+    let mut prepared = Vec::new();
+    for (path, contents) in files {
+        let temporary = format!("{path}.heitx-replace");
+        let backup = format!("{path}.heitx-backup");
+        // left over from a run that could not restore it, and maybe the
+        // only original there is
+        if Path::new(&backup).exists() {
+            remove_all(&prepared);
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("{backup} is in the way")));
+        }
+        let result = fs::write(&temporary, contents)
+            .and_then(|()| fs::metadata(path))
+            .and_then(|metadata| fs::set_permissions(&temporary, metadata.permissions()))
+            .and_then(|()| fs::hard_link(path, &backup));
+        prepared.push((path.as_str(), temporary, backup));
+        if let Err(error) = result {
+            remove_all(&prepared);
+            return Err(error);
+        }
+    }
+    for (index, (path, temporary, _)) in prepared.iter().enumerate() {
+        if let Err(error) = rename(temporary, path) {
+            // a backup that cannot be put back is the only original left
+            let unrestored: Vec<&str> = prepared.iter()
+                .take(index)
+                .filter(|(path, _, backup)| rename(backup, path).is_err())
+                .map(|(path, _, _)| *path)
+                .collect();
+            let restored: Vec<_> = prepared.iter().filter(|(path, _, _)| !unrestored.contains(path)).cloned().collect();
+            remove_all(&restored);
+            for (_, temporary, _) in &prepared {
+                let _ = fs::remove_file(temporary);
+            }
+            if unrestored.is_empty() {
+                return Err(error);
+            }
+            return Err(Error::new(error.kind(), format!("{error}, the originals of {} are kept with a .heitx-backup suffix", unrestored.join(", "))));
+        }
+    }
+    remove_all(&prepared);
+    Ok(())
+}
+
+// removes the copies and links `write_all` made that are still there
+fn remove_all(prepared: &[(&str, String, String)]) {
+    for (_, temporary, backup) in prepared {
+        let _ = fs::remove_file(temporary);
+        let _ = fs::remove_file(backup);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Error, ErrorKind};
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+    use crate::grep::{self, Grep};
+    use super::{rewrite, write_all, write_all_with, Change, Replace};
+
+    // a fresh directory for one test
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("heitx-replace-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).expect("temporary directory");
+        directory
+    }
+
+    fn file(directory: &Path, name: &str, contents: &str) -> String {
+        let path = directory.join(name);
+        fs::write(&path, contents).expect("file written");
+        path.display().to_string()
+    }
+
+    fn read(path: &str) -> String {
+        fs::read_to_string(path).expect("file read")
+    }
+
+    fn names(directory: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(directory).expect("directory listed")
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn changes(pattern: &str, replacement: &str, path: &str, contents: &str) -> Vec<Change> {
+        let hits = grep::search(&grep::pattern(pattern).expect("pattern"), path, contents);
+        Replace::new(&grep::pattern(pattern).expect("pattern"), replacement, false, hits).changes
+    }
+
+    #[test]
+    fn rewrite_replaces_on_unchanged_lines() {
+        let changes = changes("foo", "bar", "a", "foo\r\nx foo foo\n");
+        let changes: Vec<&Change> = changes.iter().collect();
+        assert_eq!(rewrite("foo\r\nx foo foo\n", &changes).as_deref(), Some("bar\r\nx bar bar\n"));
+    }
+
+    #[test]
+    fn rewrite_rejects_lines_changed_since_the_search() {
+        let changes = changes("foo", "bar", "a", "one\nfoo\n");
+        let changes: Vec<&Change> = changes.iter().collect();
+        assert_eq!(rewrite("one\nfood\n", &changes), None);
+        assert_eq!(rewrite("foo\n", &changes), None);
+    }
+
+    #[test]
+    fn dirty_open_files_are_replaced_in_what_the_buffer_holds() {
+        let directory = directory("open");
+        let path = file(&directory, "open.txt", "foo\n");
+        let unsaved = "new line\nfoo and foo\n".to_string();
+        let open = vec![(fs::canonicalize(&path).expect("file exists"), unsaved.clone())];
+        let mut search = Grep::start(&directory, grep::pattern("foo").expect("pattern"), open);
+        while search.is_searching() {
+            search.wait(Duration::from_millis(10));
+        }
+        let replace = Replace::new(&grep::pattern("foo").expect("pattern"), "bar", false, search.into_hits());
+        let files = replace.files();
+        fs::remove_dir_all(&directory).expect("temporary directory removed");
+        let [(_, changes)] = files.as_slice() else {
+            panic!("hits in more than the open file");
+        };
+        assert_eq!(rewrite(&unsaved, changes).as_deref(), Some("new line\nbar and bar\n"));
+        // the file on disk is not what was searched
+        assert_eq!(rewrite("foo\n", changes), None);
+    }
+
+    #[test]
+    fn write_all_replaces_every_file_and_cleans_up() {
+        let directory = directory("write");
+        let a = file(&directory, "a.txt", "a\n");
+        let b = file(&directory, "b.txt", "b\n");
+        write_all(&[(a.clone(), "A\n".to_string()), (b.clone(), "B\n".to_string())]).expect("files written");
+        assert_eq!((read(&a), read(&b)), ("A\n".to_string(), "B\n".to_string()));
+        assert_eq!(names(&directory), ["a.txt", "b.txt"]);
+        fs::remove_dir_all(&directory).expect("temporary directory removed");
+    }
+
+    #[test]
+    fn write_all_restores_the_originals_when_a_rename_fails() {
+        let directory = directory("restore");
+        let a = file(&directory, "a.txt", "a\n");
+        let b = file(&directory, "b.txt", "b\n");
+        let c = file(&directory, "c.txt", "c\n");
+        let files = [(a.clone(), "A\n".to_string()), (b.clone(), "B\n".to_string()), (c.clone(), "C\n".to_string())];
+        let result = write_all_with(&files, |from, to| {
+            if to == c {
+                return Err(Error::other("rename failed"));
+            }
+            fs::rename(from, to)
+        });
+        assert!(result.is_err());
+        assert_eq!((read(&a), read(&b), read(&c)), ("a\n".to_string(), "b\n".to_string(), "c\n".to_string()));
+        assert_eq!(names(&directory), ["a.txt", "b.txt", "c.txt"]);
+        fs::remove_dir_all(&directory).expect("temporary directory removed");
+    }
+
+    #[test]
+    fn write_all_keeps_a_backup_it_cannot_restore() {
+        let directory = directory("unrestored");
+        let a = file(&directory, "a.txt", "a\n");
+        let b = file(&directory, "b.txt", "b\n");
+        let files = [(a.clone(), "A\n".to_string()), (b.clone(), "B\n".to_string())];
+        // the first file's backup cannot be put back once the second fails
+        let result = write_all_with(&files, |from, to| {
+            if to == b || from.ends_with(".heitx-backup") {
+                return Err(Error::other("rename failed"));
+            }
+            fs::rename(from, to)
+        });
+        let error = result.expect_err("the second rename fails");
+        assert!(error.to_string().contains(&a));
+        assert_eq!(read(&format!("{a}.heitx-backup")), "a\n");
+        assert_eq!(read(&b), "b\n");
+        // and is not overwritten by the next replace
+        let again = write_all(&[(a.clone(), "A\n".to_string())]).expect_err("the backup is in the way");
+        assert_eq!(again.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(read(&format!("{a}.heitx-backup")), "a\n");
+        fs::remove_dir_all(&directory).expect("temporary directory removed");
+    }
+}
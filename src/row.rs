@@ -1,16 +1,26 @@
 
 use std::cmp;
-use termion::color;
+use std::ops::Range;
+use termion::{color, style};
 use unicode_segmentation::UnicodeSegmentation;
 use crate::{HighlightingOptions, SearchDirection};
-use crate::highlighting;
+use crate::filetype::{BlockComment, NumberRules, StringDelimiter};
+use crate::highlighting::{self, State};
 
 #[derive(Default)]
 pub struct Row {
     string: String,
     highlighting: Vec<highlighting::Type>,
+    matches: Vec<Range<usize>>,
+    // bracket under the cursor and its partner
+    brackets: Vec<(usize, highlighting::Type)>,
+    // selected graphemes, drawn inverted
+    selection: Option<Range<usize>>,
     len: usize,
-    pub is_highlighted: bool,
+    is_highlighted: bool,
+    // lexer state the cached highlighting started from and ended in
+    start_state: State,
+    end_state: State,
 }
 
 impl From<&str> for Row {
@@ -18,8 +28,13 @@ impl From<&str> for Row {
         Self {
             string: String::from(slice),
             highlighting: Vec::new(),
+            matches: Vec::new(),
+            brackets: Vec::new(),
+            selection: None,
             len: slice.graphemes(true).count(),
             is_highlighted: false,
+            start_state: State::Normal,
+            end_state: State::Normal,
         }
     }
 }
@@ -30,26 +45,42 @@ impl Row {
         let start = cmp::min(start, end);
         let mut res = String::new();
         let mut current_highlighting = &highlighting::Type::None;
-        #[allow(clippy::integer_arithmetic)]
+        let mut inverted = false;
+        #[allow(clippy::arithmetic_side_effects)]
         for (index, grapheme) in self.string[..]
             .graphemes(true)
             .enumerate()
             .skip(start)
             .take(end - start) {
             if let Some(c) = grapheme.chars().next() {
-                let highlighting_type = self.highlighting.get(index).unwrap_or(&highlighting::Type::None);
+                let bracket = self.brackets.iter().find(|(bracket, _)| *bracket == index);
+                let highlighting_type = if self.matches.iter().any(|range| range.contains(&index)) {
+                    &highlighting::Type::Match
+                } else if let Some((_, hl_type)) = bracket {
+                    hl_type
+                } else {
+                    self.highlighting.get(index).unwrap_or(&highlighting::Type::None)
+                };
+                let selected = self.selection.as_ref().is_some_and(|range| range.contains(&index));
+                if selected != inverted {
+                    inverted = selected;
+                    res.push_str(if selected { style::Invert.as_ref() } else { style::NoInvert.as_ref() });
+                }
                 if highlighting_type != current_highlighting {
                     current_highlighting = highlighting_type;
                     let start_highlight = format!("{}", termion::color::Fg(highlighting_type.to_color()));
                     res.push_str(&start_highlight[..]);
                 }
                 if c == '\t' {
-                    res.push_str(" ");
+                    res.push(' ');
                 } else {
                     res.push(c);
                 }
             }
         }
+        if inverted {
+            res.push_str(style::NoInvert.as_ref());
+        }
         let end_highlight = format!("{}", termion::color::Fg(color::Reset));
         res.push_str(&end_highlight[..]);
         res
@@ -64,6 +95,7 @@ impl Row {
     }
 
     pub fn insert(&mut self, pos: usize, c: char) {
+        self.is_highlighted = false;
         if pos >= self.len {
             self.string.push(c);
             self.len += 1;
@@ -83,15 +115,15 @@ impl Row {
         self.string = res;
     }
 
-    #[allow(clippy::integer_arithmetic)]
+    #[allow(clippy::arithmetic_side_effects)]
     pub fn delete(&mut self, pos: usize) {
         if pos >= self.len {
             return;
         }
+        self.is_highlighted = false;
         let mut res: String = String::new();
         let mut len = 0;
         for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            len += 1;
             if index != pos {
                 len += 1;
                 res.push_str(grapheme);
@@ -104,6 +136,7 @@ impl Row {
     pub fn append(&mut self, new: &Self) {
         self.string = format!("{}{}", self.string, new.string);
         self.len += new.len;
+        self.is_highlighted = false;
     }
 
     pub fn split(&mut self, pos: usize) -> Self {
@@ -126,15 +159,81 @@ impl Row {
         Self {
             string: split_row,
             highlighting: Vec::new(),
+            matches: Vec::new(),
+            brackets: Vec::new(),
+            selection: None,
             len: split_len,
             is_highlighted: false,
+            start_state: State::Normal,
+            end_state: State::Normal,
         }
     }
 
+    // leading whitespace
+    pub fn indentation(&self) -> &str {
+        let content = self.string.trim_start();
+        &self.string[..self.string.len().saturating_sub(content.len())]
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
     }
 
+    pub fn grapheme(&self, index: usize) -> Option<&str> {
+        self.string[..].graphemes(true).nth(index)
+    }
+
+    // byte offset of the grapheme at `index`, or the row length past the end
+    #[cfg(feature = "tree-sitter")]
+    pub fn byte_index(&self, index: usize) -> usize {
+        self.string[..]
+            .grapheme_indices(true)
+            .nth(index)
+            .map_or(self.string.len(), |(byte_index, _)| byte_index)
+    }
+
+    // grapheme ranges of the words in the row, split into camelCase and
+    // snake_case parts when `subwords` is set
+    fn words(&self, subwords: bool) -> Vec<Range<usize>> {
+        let offsets: Vec<usize> = self.string.grapheme_indices(true).map(|(index, _)| index).collect();
+        let grapheme_at = |byte: usize| offsets.partition_point(|offset| *offset < byte);
+        let mut words = Vec::new();
+        for (index, word) in self.string.split_word_bound_indices() {
+            if !word.chars().any(is_identifier) {
+                continue;
+            }
+            let start = grapheme_at(index);
+            if subwords {
+                words.extend(subword_ranges(word).into_iter().map(|range| range.start + start..range.end + start));
+            } else {
+                words.push(start..grapheme_at(index.saturating_add(word.len())));
+            }
+        }
+        words
+    }
+
+    // where a word motion from `at` stops: the end of the next word going
+    // forward, the start of the previous one going backward, the row edge
+    // when no word is left and None when already there
+    pub fn word_boundary(&self, at: usize, direction: SearchDirection, subwords: bool) -> Option<usize> {
+        let words = self.words(subwords);
+        match direction {
+            SearchDirection::Forward => words.iter()
+                .find(|word| word.end > at)
+                .map(|word| word.end)
+                .or((at < self.len).then_some(self.len)),
+            SearchDirection::Backward => words.iter()
+                .rev()
+                .find(|word| word.start < at)
+                .map(|word| word.start)
+                .or((at > 0).then_some(0)),
+        }
+    }
+
     pub fn search(&self, query: &str, after: usize, direction: SearchDirection) -> Option<usize> {
         if after > self.len || query.is_empty() {
             return None;
@@ -169,7 +268,10 @@ impl Row {
         None
     }
 
-    fn highlight_match(&mut self, word: &Option<String>) {
+    // search matches are kept apart from the lexer output so the cached
+    // highlighting survives a changing search word
+    pub fn highlight_match(&mut self, word: &Option<String>) {
+        self.matches.clear();
         if let Some(word) = word {
             if word.is_empty() {
                 return;
@@ -177,10 +279,7 @@ impl Row {
             let mut index = 0;
             while let Some(search_match) = self.search(word, index, SearchDirection::Forward) {
                 if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count()) {
-                    #[allow(clippy::indexing_slicing)]
-                    for i in index.saturating_add(search_match)..next_index {
-                        self.highlighting[i] = highlighting::Type::Match;
-                    }
+                    self.matches.push(search_match..next_index);
                     index = next_index;
                 } else {
                     break;
@@ -189,102 +288,477 @@ impl Row {
         }
     }
 
-    fn highlight_char(&mut self, index: &mut usize, opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
-        if opts.characters() && c == '\'' {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                let closing_index = if *next_char == '\\' {
-                    index.saturating_add(3)
-                } else {
-                    index.saturating_add(2)
-                };
-                if let Some(closing_char) = chars.get(closing_index) {
-                    if *closing_char == '\'' {
-                        for _ in 0..=closing_index.saturating_sub(*index) {
-                            self.highlighting.push(highlighting::Type::Character);
-                            *index += 1;
-                        }
-                        return true;
-                    }
-                }
+    pub fn set_selection(&mut self, selection: Option<Range<usize>>) {
+        self.selection = selection;
+    }
+
+    pub fn mark_bracket(&mut self, index: usize, hl_type: highlighting::Type) {
+        self.brackets.push((index, hl_type));
+    }
+
+    pub fn clear_brackets(&mut self) {
+        self.brackets.clear();
+    }
+
+    pub fn highlighting_at(&self, index: usize) -> Option<highlighting::Type> {
+        self.highlighting.get(index).copied()
+    }
+
+    // false inside strings and comments, going by the last highlighting
+    pub fn is_code(&self, index: usize) -> bool {
+        self.highlighting.get(index).is_none_or(|hl_type| !hl_type.is_comment_or_string())
+    }
+
+    fn highlight_char(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if !opts.characters() {
+            return false;
+        }
+        let prefix_len = opts.character_prefixes().iter()
+            .filter(|prefix| starts_with_at(chars, *index, prefix))
+            .map(|prefix| prefix.chars().count())
+            .max()
+            .unwrap_or(0);
+        if prefix_len > 0 && !follows_separator(chars, *index) {
+            return false;
+        }
+        let quote = index.saturating_add(prefix_len);
+        if chars.get(quote) != Some(&'\'') {
+            return false;
+        }
+        let closing_index = match chars.get(quote.saturating_add(1)) {
+            // escapes such as '\n', '\'' or '\u{1F600}'
+            Some('\\') => (quote.saturating_add(3)..cmp::min(chars.len(), quote.saturating_add(12)))
+                .find(|i| chars.get(*i) == Some(&'\'')),
+            Some(_) => Some(quote.saturating_add(2)).filter(|i| chars.get(*i) == Some(&'\'')),
+            None => None,
+        };
+        if let Some(closing_index) = closing_index {
+            self.push_highlighting(index, closing_index.saturating_add(1).saturating_sub(*index), highlighting::Type::Character);
+            return true;
+        }
+        false
+    }
+
+    // `'a` when it is not a character literal
+    fn highlight_lifetime(&mut self, index: &mut usize, opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
+        if opts.lifetimes() && c == '\'' {
+            let len = identifier_len(chars, index.saturating_add(1));
+            if len > 0 {
+                self.push_highlighting(index, len.saturating_add(1), highlighting::Type::Lifetime);
+                return true;
             }
         }
         false
     }
 
-    fn highlight_comment(&mut self, index: &mut usize, opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
-        if opts.comments() && c == '/' && *index < chars.len() {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                if *next_char == '/' {
-                    for _ in *index..chars.len() {
-                        self.highlighting.push(highlighting::Type::Comment);
-                        *index += 1;
-                    }
-                    return true;
+    fn highlight_attribute(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        let Some(prefix) = opts.attributes().iter().find(|prefix| starts_with_at(chars, *index, prefix)) else {
+            return false;
+        };
+        let start = index.saturating_add(prefix.chars().count());
+        let mut end = start;
+        if prefix.ends_with('[') {
+            // `[[nodiscard]]` needs both brackets closed
+            let mut depth = prefix.matches('[').count();
+            while let Some(c) = chars.get(end) {
+                end = end.saturating_add(1);
+                match c {
+                    '[' => depth = depth.saturating_add(1),
+                    ']' => depth = depth.saturating_sub(1),
+                    _ => (),
+                }
+                if depth == 0 {
+                    break;
                 }
             }
+        } else {
+            // annotation names may be qualified, `@java.lang.Override`
+            while chars.get(end).is_some_and(|c| *c == '.' || is_identifier(*c)) {
+                end = end.saturating_add(1);
+            }
+            if end == start {
+                return false;
+            }
+        }
+        self.push_highlighting(index, end.saturating_sub(*index), highlighting::Type::Attribute);
+        true
+    }
+
+    fn highlight_macro(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if !opts.macros() || !follows_separator(chars, *index) {
+            return false;
+        }
+        let len = identifier_len(chars, *index);
+        let bang = index.saturating_add(len);
+        if len > 0 && chars.get(bang) == Some(&'!') && chars.get(bang.saturating_add(1)) != Some(&'=') {
+            self.push_highlighting(index, len.saturating_add(1), highlighting::Type::Macro);
+            return true;
+        }
+        false
+    }
+
+    fn highlight_doc_comment(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if opts.doc_comments().iter().any(|token| starts_with_at(chars, *index, token)) {
+            self.push_highlighting(index, chars.len().saturating_sub(*index), highlighting::Type::DocComment);
+            return true;
+        }
+        false
+    }
+
+    fn highlight_comment(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if opts.line_comments().iter().any(|token| starts_with_at(chars, *index, token)) {
+            self.push_highlighting(index, chars.len().saturating_sub(*index), highlighting::Type::Comment);
+            return true;
         }
         false
     }
 
-    fn highlight_multiple_comments(&mut self, index: &mut usize, opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
-        if opts.comments() && c == '/' && *index < chars.len() {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                if *next_char == '*' {
-                    let closing_index = if let Some(closing_index) = self.string[*index+ 2..].find("*/") {
-                        *index + closing_index + 4
-                    } else {
-                        chars.len()
-                    };
-                    for _ in *index..closing_index {
-                        self.highlighting.push(highlighting::Type::MultipleComments);
-                        *index += 1;
-                    }
-                    return true;
+    fn highlight_multiple_comments(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> Option<State> {
+        let (comment_index, comment) = opts.block_comments().iter()
+            .enumerate()
+            .filter(|(_, comment)| starts_with_at(chars, *index, &comment.start))
+            .max_by_key(|(_, comment)| comment.start.len())?;
+        self.push_highlighting(index, comment.start.chars().count(), comment_type(comment));
+        Some(self.highlight_comment_body(index, opts, chars, comment_index, 1))
+    }
+
+    // scans until the comment is closed, nested openings deepen it
+    fn highlight_comment_body(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char], comment_index: usize, mut depth: usize) -> State {
+        let Some(comment) = opts.block_comments().get(comment_index) else {
+            return State::Normal;
+        };
+        let hl_type = comment_type(comment);
+        while *index < chars.len() {
+            // `/* /** */ */` nests too, any opener sharing the closer counts
+            let opener = opts.block_comments().iter()
+                .filter(|other| other.end == comment.end && starts_with_at(chars, *index, &other.start))
+                .map(|other| other.start.chars().count())
+                .max();
+            if starts_with_at(chars, *index, &comment.end) {
+                self.push_highlighting(index, comment.end.chars().count(), hl_type);
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return State::Normal;
                 }
+            } else if let Some(len) = opener.filter(|_| comment.nested) {
+                self.push_highlighting(index, len, hl_type);
+                depth = depth.saturating_add(1);
+            } else {
+                self.push_highlighting(index, 1, hl_type);
+            }
+        }
+        State::BlockComment(comment_index, depth)
+    }
+
+    fn highlight_string(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> Option<State> {
+        // `f"` or `rb'''`, the prefix belongs to the string
+        let prefix_len = opts.string_prefixes().iter()
+            .filter(|prefix| starts_with_at(chars, *index, prefix))
+            .map(|prefix| prefix.chars().count())
+            .filter(|len| opts.strings().iter().any(|delimiter| starts_with_at(chars, index.saturating_add(*len), &delimiter.start)))
+            .max()
+            .filter(|_| follows_separator(chars, *index))
+            .unwrap_or(0);
+        let start = index.saturating_add(prefix_len);
+        let (string_index, delimiter) = opts.strings().iter()
+            .enumerate()
+            .filter(|(_, delimiter)| starts_with_at(chars, start, &delimiter.start))
+            .max_by_key(|(_, delimiter)| delimiter.start.len())?;
+        // `b"` is a byte string, `ab"` is not
+        if delimiter.start.starts_with(is_identifier) && !follows_separator(chars, *index) {
+            return None;
+        }
+        self.push_highlighting(index, prefix_len.saturating_add(delimiter.start.chars().count()), delimiter.hl_type());
+        Some(self.highlight_string_body(index, chars, string_index, delimiter))
+    }
+
+    fn highlight_string_body(&mut self, index: &mut usize, chars: &[char], string_index: usize, delimiter: &StringDelimiter) -> State {
+        while let Some(c) = chars.get(*index) {
+            let variable = delimiter.interpolation.as_deref().and_then(|prefix| variable_len(chars, *index, prefix));
+            if Some(*c) == delimiter.escape {
+                self.push_highlighting(index, escape_len(chars, *index), highlighting::Type::Escape);
+            } else if let Some(len) = variable {
+                self.push_highlighting(index, len, highlighting::Type::Variable);
+            } else if starts_with_at(chars, *index, delimiter.end()) {
+                self.push_highlighting(index, delimiter.end().chars().count(), delimiter.hl_type());
+                return State::Normal;
+            } else {
+                self.push_highlighting(index, 1, delimiter.hl_type());
             }
         }
+        if delimiter.multiline {
+            State::String(string_index)
+        } else {
+            State::Normal
+        }
+    }
+
+    fn highlight_raw_string(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> Option<State> {
+        let prefix = opts.raw_strings().iter()
+            .filter(|prefix| starts_with_at(chars, *index, prefix))
+            .max_by_key(|prefix| prefix.len())?;
+        if !follows_separator(chars, *index) {
+            return None;
+        }
+        let mut quote = index.saturating_add(prefix.chars().count());
+        let mut hashes = 0;
+        while chars.get(quote) == Some(&'#') {
+            hashes += 1;
+            quote = quote.saturating_add(1);
+        }
+        if chars.get(quote) != Some(&'"') {
+            // `r#type` is a raw identifier rather than a keyword
+            let len = identifier_len(chars, quote);
+            if hashes == 1 && len > 0 {
+                self.push_highlighting(index, quote.saturating_add(len).saturating_sub(*index), highlighting::Type::None);
+                return Some(State::Normal);
+            }
+            return None;
+        }
+        self.push_highlighting(index, quote.saturating_add(1).saturating_sub(*index), highlighting::Type::String);
+        Some(self.highlight_raw_string_body(index, chars, hashes))
+    }
+
+    // raw strings have no escapes, only `"` followed by the opening hashes closes them
+    fn highlight_raw_string_body(&mut self, index: &mut usize, chars: &[char], hashes: usize) -> State {
+        while let Some(c) = chars.get(*index) {
+            let closing = *c == '"' && (1..=hashes).all(|i| chars.get(index.saturating_add(i)) == Some(&'#'));
+            if closing {
+                self.push_highlighting(index, hashes.saturating_add(1), highlighting::Type::String);
+                return State::Normal;
+            }
+            self.push_highlighting(index, 1, highlighting::Type::String);
+        }
+        State::RawString(hashes)
+    }
+
+    // picks up a comment or string left open by the previous row
+    fn highlight_continuation(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char], state: State) -> State {
+        match state {
+            State::BlockComment(comment_index, depth) => {
+                return self.highlight_comment_body(index, opts, chars, comment_index, depth);
+            },
+            State::String(string_index) => {
+                if let Some(delimiter) = opts.strings().get(string_index) {
+                    return self.highlight_string_body(index, chars, string_index, delimiter);
+                }
+            },
+            State::RawString(hashes) => return self.highlight_raw_string_body(index, chars, hashes),
+            State::Heredoc(terminator) => {
+                // `<<-` lets the terminator be indented with tabs
+                let closed = self.string.trim_start_matches('\t') == terminator;
+                self.push_highlighting(index, chars.len(), highlighting::Type::String);
+                return if closed { State::Normal } else { State::Heredoc(terminator) };
+            },
+            State::Normal => (),
+        }
+        State::Normal
+    }
+
+    fn highlight_variable(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if let Some(len) = opts.variables().iter().find_map(|prefix| variable_len(chars, *index, prefix)) {
+            self.push_highlighting(index, len, highlighting::Type::Variable);
+            return true;
+        }
         false
     }
 
-    fn highlight_string(&mut self, index: &mut usize, opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
-        if opts.strings() && c == '"' {
+    // `<<EOF`, `<<-EOF` or `<<'EOF'`, returns the terminator; the body starts on the next row
+    fn highlight_heredoc(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> Option<String> {
+        if !opts.heredocs() || !starts_with_at(chars, *index, "<<") || chars.get(index.saturating_add(2)) == Some(&'<') {
+            return None;
+        }
+        let mut start = index.saturating_add(2);
+        if chars.get(start) == Some(&'-') {
+            start = start.saturating_add(1);
+        }
+        while chars.get(start) == Some(&' ') {
+            start = start.saturating_add(1);
+        }
+        let quote = chars.get(start).copied().filter(|c| *c == '\'' || *c == '"');
+        if quote.is_some() {
+            start = start.saturating_add(1);
+        }
+        let len = identifier_len(chars, start);
+        if len == 0 {
+            return None;
+        }
+        let mut end = start.saturating_add(len);
+        if let Some(quote) = quote {
+            if chars.get(end) != Some(&quote) {
+                return None;
+            }
+            end = end.saturating_add(1);
+        }
+        let terminator = chars.iter().skip(start).take(len).collect();
+        self.push_highlighting(index, end.saturating_sub(*index), highlighting::Type::String);
+        Some(terminator)
+    }
+
+    // `"key": 1`, `key = 1` or `- key: 1`
+    fn highlight_key(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if opts.key_separators().is_empty() || !at_key_position(chars, *index) {
+            return false;
+        }
+        let quote = chars.get(*index).copied().filter(|c| *c == '"' || *c == '\'');
+        let end = if let Some(quote) = quote {
+            let mut end = index.saturating_add(1);
             loop {
-                self.highlighting.push(highlighting::Type::String);
-                *index += 1;
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char == '"' {
-                        break;
-                    }
-                } else {
-                    break;
+                match chars.get(end) {
+                    Some('\\') => end = end.saturating_add(2),
+                    Some(c) if *c == quote => break end.saturating_add(1),
+                    Some(_) => end = end.saturating_add(1),
+                    None => return false,
                 }
             }
-            self.highlighting.push(highlighting::Type::String);
-            *index += 1;
-            return true;
+        } else {
+            // `<<` is YAML's merge key
+            index.saturating_add(chars.iter().skip(*index).take_while(|c| is_identifier(**c) || matches!(c, '-' | '.' | '<')).count())
+        };
+        if end == *index {
+            return false;
+        }
+        let separator = chars.iter().skip(end).position(|c| !c.is_whitespace()).map(|offset| end.saturating_add(offset));
+        let Some(separator) = separator.filter(|i| chars.get(*i).is_some_and(|c| opts.key_separators().contains(c))) else {
+            return false;
+        };
+        // a bare `key:` is followed by a space, `http://` is not a key
+        if quote.is_none() && chars.get(separator) == Some(&':')
+            && !chars.get(separator.saturating_add(1)).is_none_or(|c| c.is_whitespace()) {
+            return false;
+        }
+        self.push_highlighting(index, end.saturating_sub(*index), highlighting::Type::Key);
+        true
+    }
+
+    // `[table]` and `[[array.of.tables]]` at the start of a row
+    fn highlight_table_header(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if !opts.table_headers() || chars.get(*index) != Some(&'[') || !at_row_start(chars, *index) {
+            return false;
+        }
+        let Some(close) = chars.iter().skip(*index).position(|c| *c == ']').map(|offset| index.saturating_add(offset)) else {
+            return false;
+        };
+        let end = close.saturating_add(chars.iter().skip(close).take_while(|c| **c == ']').count());
+        self.push_highlighting(index, end.saturating_sub(*index), highlighting::Type::Heading);
+        true
+    }
+
+    // `#` to `######` followed by a space
+    fn highlight_heading(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if !opts.headings() || *index != 0 {
+            return false;
+        }
+        let level = chars.iter().take_while(|c| **c == '#').count();
+        if !(1..=6).contains(&level) || !chars.get(level).is_none_or(|c| c.is_whitespace()) {
+            return false;
+        }
+        self.push_highlighting(index, chars.len(), highlighting::Type::Heading);
+        true
+    }
+
+    fn highlight_anchor(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if !index.checked_sub(1).and_then(|i| chars.get(i)).is_none_or(|c| c.is_whitespace()) {
+            return false;
+        }
+        let Some(prefix) = opts.anchors().iter().find(|prefix| starts_with_at(chars, *index, prefix)) else {
+            return false;
+        };
+        let start = index.saturating_add(prefix.chars().count());
+        let len = chars.iter().skip(start).take_while(|c| is_identifier(**c) || **c == '-' || **c == '.').count();
+        if len == 0 {
+            return false;
+        }
+        self.push_highlighting(index, start.saturating_add(len).saturating_sub(*index), highlighting::Type::Anchor);
+        true
+    }
+
+    // the delimiters hug the text, `a * b` and `snake_case` are not emphasis
+    fn highlight_emphasis(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        if !follows_separator(chars, *index) {
+            return false;
+        }
+        let mut delimiters: Vec<&String> = opts.emphasis().iter().filter(|token| starts_with_at(chars, *index, token)).collect();
+        delimiters.sort_by_key(|token| cmp::Reverse(token.len()));
+        for token in delimiters {
+            let len = token.chars().count();
+            let start = index.saturating_add(len);
+            if chars.get(start).is_none_or(|c| c.is_whitespace()) {
+                continue;
+            }
+            let close = (start.saturating_add(1)..chars.len()).find(|i| {
+                starts_with_at(chars, *i, token)
+                    && chars.get(i.saturating_sub(1)).is_some_and(|c| !c.is_whitespace())
+                    && chars.get(i.saturating_add(len)).is_none_or(|c| is_separators(*c))
+            });
+            if let Some(close) = close {
+                self.push_highlighting(index, close.saturating_add(len).saturating_sub(*index), highlighting::Type::Emphasis);
+                return true;
+            }
         }
         false
     }
 
+    // function names, capitalized type names and ALL_CAPS constants
+    fn highlight_identifier(&mut self, index: &mut usize, opts: &HighlightingOptions, chars: &[char]) -> bool {
+        let Some(rules) = opts.identifiers() else {
+            return false;
+        };
+        let len = identifier_len(chars, *index);
+        if len == 0 || !follows_separator(chars, *index) {
+            return false;
+        }
+        let word: String = chars.iter().skip(*index).take(len).collect();
+        let end = index.saturating_add(len);
+        let hl_type = if rules.constants() && is_constant(&word) {
+            highlighting::Type::Constant
+        } else if rules.types() && word.starts_with(char::is_uppercase) {
+            highlighting::Type::Class
+        } else if rules.functions() && (chars.get(end) == Some(&'(') || starts_with_at(chars, end, "::<")
+            || preceding_word(chars, *index).is_some_and(|word| rules.definitions().contains(&word))) {
+            highlighting::Type::Function
+        } else {
+            highlighting::Type::None
+        };
+        self.push_highlighting(index, len, hl_type);
+        true
+    }
+
+    fn highlight_angle_bracket(&mut self, index: &mut usize, angle_brackets: &[usize]) -> bool {
+        if !angle_brackets.contains(index) {
+            return false;
+        }
+        self.push_highlighting(index, 1, highlighting::Type::Punctuation);
+        true
+    }
+
+    fn highlight_operator(&mut self, index: &mut usize, opts: &HighlightingOptions, c: char) -> bool {
+        let hl_type = if opts.operators().contains(c) {
+            highlighting::Type::Operator
+        } else if opts.punctuation().contains(c) {
+            highlighting::Type::Punctuation
+        } else {
+            return false;
+        };
+        self.push_highlighting(index, 1, hl_type);
+        true
+    }
+
     fn highlight_number(&mut self, index: &mut usize, opts: &HighlightingOptions, c: char, chars: &[char]) -> bool {
-        if opts.numbers() && c.is_ascii_digit() {
+        if let Some(rules) = opts.numbers() {
+            if !c.is_ascii_digit() {
+                return false;
+            }
             if *index > 0 {
-                #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+                #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
                 let prev_char = chars[*index - 1];
                 if !is_separators(prev_char) {
                     return false;
                 }
             }
-            loop {
+            for _ in *index..number_end(chars, *index, rules) {
                 self.highlighting.push(highlighting::Type::Number);
                 *index += 1;
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char != '.' && !next_char.is_ascii_digit() {
-                        break;
-                    }
-                } else {
-                    break;
-                }
             }
             return true;
         }
@@ -313,7 +787,7 @@ impl Row {
 
     fn highlight_keywords(&mut self, index: &mut usize, chars: &[char], keywords: &[String], hl_type: highlighting::Type) -> bool {
         if *index > 0 {
-            #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+            #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
                 let prev_char = chars[*index - 1];
             if !is_separators(prev_char) {
                 return false;
@@ -322,13 +796,13 @@ impl Row {
 
         for word in keywords {
             if *index < chars.len().saturating_sub(word.len()) {
-                #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+                #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
                     let next_char = chars[*index + word.len()];
                 if !is_separators(next_char) {
                     continue;
                 }
             }
-            if self.highlight_str(index, &word, chars, hl_type) {
+            if self.highlight_str(index, word, chars, hl_type) {
                 return true;
             }
         }
@@ -343,56 +817,665 @@ impl Row {
         self.highlight_keywords(index, chars, opts.secondary_keywords(), highlighting::Type::SecondaryKeywords)
     }
 
-    pub fn highlight(&mut self, opts: &HighlightingOptions, word: &Option<String>, start_with_comment: bool) -> bool {
-        let chars: Vec<char> = self.string.chars().collect();
-        if self.is_highlighted && word.is_none() {
-            if let Some(hl_type) = self.highlighting.last() {
-                if *hl_type == highlighting::Type::MultipleComments && self.string.len() > 1 && self.string[self.string.len() - 2..] == *"*/" {
-                    return true;
-                }
-            }
-            return false;
+    fn push_highlighting(&mut self, index: &mut usize, len: usize, hl_type: highlighting::Type) {
+        for _ in 0..len {
+            self.highlighting.push(hl_type);
+            *index += 1;
+        }
+    }
+
+    // `spans` are byte ranges within the row, later spans win
+    #[cfg(feature = "tree-sitter")]
+    pub fn set_highlighting(&mut self, spans: &[(usize, usize, highlighting::Type)]) {
+        self.highlighting = self.string
+            .char_indices()
+            .map(|(byte_index, _)| {
+                spans.iter()
+                    .rev()
+                    .find(|(start, end, _)| (*start..*end).contains(&byte_index))
+                    .map_or(highlighting::Type::None, |(_, _, hl_type)| *hl_type)
+            })
+            .collect();
+    }
+
+    pub fn highlight(&mut self, opts: &HighlightingOptions, start_state: State) -> State {
+        if self.is_highlighted && self.start_state == start_state {
+            return self.end_state.clone();
         }
+        let chars: Vec<char> = self.string.chars().collect();
         self.highlighting = Vec::new();
         let mut index = 0;
-        let mut in_ml_comment = start_with_comment;
-        if in_ml_comment {
-            let closing_index = if let Some(closing_index) = self.string.find("*/") {
-                closing_index + 2
-            } else {
-                chars.len()
-            };
-            for _ in 0..closing_index {
-                self.highlighting.push(highlighting::Type::MultipleComments);
-            }
-            index = closing_index;
-        }
+        let mut state = self.highlight_continuation(&mut index, opts, &chars, start_state.clone());
+        let mut heredoc = None;
+        let angle_brackets = if opts.generics() { angle_brackets(&chars) } else { Vec::new() };
         while let Some(c) = chars.get(index) {
-            if self.highlight_multiple_comments(&mut index, &opts, *c, &chars) {
-                in_ml_comment = true;
+            if let Some(next_state) = self.highlight_multiple_comments(&mut index, opts, &chars) {
+                state = next_state;
+                continue;
+            }
+            if self.highlight_heading(&mut index, opts, &chars)
+                || self.highlight_table_header(&mut index, opts, &chars)
+                || self.highlight_key(&mut index, opts, &chars) {
                 continue;
             }
-            in_ml_comment = false;
-            if self.highlight_char(&mut index, opts, *c, &chars)
-                || self.highlight_comment(&mut index, opts, *c, &chars)
-                || self.highlight_string(&mut index, opts, *c, &chars)
+            if let Some(next_state) = self.highlight_raw_string(&mut index, opts, &chars)
+                .or_else(|| self.highlight_string(&mut index, opts, &chars)) {
+                state = next_state;
+                continue;
+            }
+            if let Some(terminator) = self.highlight_heredoc(&mut index, opts, &chars) {
+                heredoc.get_or_insert(terminator);
+                continue;
+            }
+            if self.highlight_variable(&mut index, opts, &chars)
+                || self.highlight_char(&mut index, opts, &chars)
+                || self.highlight_lifetime(&mut index, opts, *c, &chars)
+                || self.highlight_doc_comment(&mut index, opts, &chars)
+                || self.highlight_comment(&mut index, opts, &chars)
+                || self.highlight_attribute(&mut index, opts, &chars)
+                || self.highlight_anchor(&mut index, opts, &chars)
+                || self.highlight_emphasis(&mut index, opts, &chars)
                 || self.highlight_number(&mut index, opts, *c, &chars)
-                || self.highlight_primary_keywords(&mut index, &opts, &chars)
-                || self.highlight_secondary_keywords(&mut index, &opts, &chars) {
+                || self.highlight_macro(&mut index, opts, &chars)
+                || self.highlight_primary_keywords(&mut index, opts, &chars)
+                || self.highlight_secondary_keywords(&mut index, opts, &chars)
+                || self.highlight_identifier(&mut index, opts, &chars)
+                || self.highlight_angle_bracket(&mut index, &angle_brackets)
+                || self.highlight_operator(&mut index, opts, *c) {
                 continue;
             }
             self.highlighting.push(highlighting::Type::None);
             index += 1;
         }
-        self.highlight_match(word);
-        if in_ml_comment && &self.string[self.string.len().saturating_sub(2)..] != "*/" {
-            return true;
+        if let Some(terminator) = heredoc.filter(|_| state == State::Normal) {
+            state = State::Heredoc(terminator);
         }
         self.is_highlighted = true;
-        false
+        self.start_state = start_state;
+        self.end_state = state.clone();
+        state
+    }
+
+    pub fn invalidate_highlighting(&mut self) {
+        self.is_highlighted = false;
     }
 }
 
 fn is_separators(c: char) -> bool {
-    c.is_ascii_punctuation() || c.is_ascii_whitespace()
-}
\ No newline at end of file
+    (c.is_ascii_punctuation() && c != '_') || c.is_ascii_whitespace()
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// graphemes ranges of `fooBar`, `HTTPServer` and `foo_bar` parts of a word,
+// underscores belong to no part
+fn subword_ranges(word: &str) -> Vec<Range<usize>> {
+    let chars: Vec<char> = word.graphemes(true).map(|grapheme| grapheme.chars().next().unwrap_or(' ')).collect();
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (index, c) in chars.iter().enumerate() {
+        if *c == '_' {
+            if let Some(start) = start.take() {
+                ranges.push(start..index);
+            }
+            continue;
+        }
+        let previous = index.checked_sub(1).and_then(|previous| chars.get(previous));
+        let next = chars.get(index.saturating_add(1));
+        let boundary = c.is_uppercase() && previous.is_some_and(|previous| {
+            previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next.is_some_and(|next| next.is_lowercase()))
+        });
+        match start {
+            Some(word_start) if boundary => {
+                ranges.push(word_start..index);
+                start = Some(index);
+            },
+            None => start = Some(index),
+            Some(_) => (),
+        }
+    }
+    if let Some(start) = start {
+        ranges.push(start..chars.len());
+    }
+    ranges
+}
+
+fn follows_separator(chars: &[char], index: usize) -> bool {
+    index == 0 || chars.get(index.saturating_sub(1)).is_some_and(|c| is_separators(*c))
+}
+
+fn at_row_start(chars: &[char], index: usize) -> bool {
+    chars.iter().take(index).all(|c| c.is_whitespace())
+}
+
+// keys open a row, a YAML list item or follow `{` or `,`
+fn at_key_position(chars: &[char], index: usize) -> bool {
+    let before = chars.iter().take(index).rposition(|c| !c.is_whitespace());
+    match before.and_then(|i| chars.get(i).map(|c| (i, *c))) {
+        None | Some((_, '{' | ',')) => true,
+        Some((i, '-')) => at_row_start(chars, i),
+        Some(_) => false,
+    }
+}
+
+// length of the identifier starting at `index`, 0 if there is none
+fn identifier_len(chars: &[char], index: usize) -> usize {
+    if chars.get(index).is_some_and(char::is_ascii_digit) {
+        return 0;
+    }
+    chars.iter().skip(index).take_while(|c| is_identifier(**c)).count()
+}
+
+// length of the expansion at `index`: `$name`, `${...}`, `$(...)`, `$1` or `$?`,
+// a prefix ending in a bracket runs to the matching closer
+fn variable_len(chars: &[char], index: usize, prefix: &str) -> Option<usize> {
+    if !starts_with_at(chars, index, prefix) {
+        return None;
+    }
+    let mut start = index.saturating_add(prefix.chars().count());
+    let is_open = |c: &char| matches!(c, '{' | '(');
+    let open = prefix.chars().next_back().filter(is_open).or_else(|| {
+        let open = chars.get(start).copied().filter(is_open);
+        if open.is_some() {
+            start = start.saturating_add(1);
+        }
+        open
+    });
+    if let Some(open) = open {
+        let close = if open == '(' { ')' } else { '}' };
+        let mut depth = 1_usize;
+        let mut end = start;
+        while let Some(c) = chars.get(end) {
+            end = end.saturating_add(1);
+            if *c == open {
+                depth = depth.saturating_add(1);
+            } else if *c == close {
+                depth = depth.saturating_sub(1);
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+        return Some(end.saturating_sub(index));
+    }
+    let len = match chars.get(start) {
+        // `$<` and `$^` are make automatic variables
+        Some(c) if c.is_ascii_digit() || "@?#$!*-<^+%".contains(*c) => 1,
+        _ => identifier_len(chars, start),
+    };
+    (len > 0).then(|| start.saturating_add(len).saturating_sub(index))
+}
+
+// indices of the `<` and `>` around type arguments and parameters, as in
+// `Map<K, List<V>>`, `<T> void` or the `<>` diamond, told from comparisons
+// by what is between them
+fn angle_brackets(chars: &[char]) -> Vec<usize> {
+    let mut brackets = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = chars.iter().skip(start).position(|c| *c == '<') {
+        let open = start.saturating_add(offset);
+        let found = type_arguments(chars, open);
+        start = found.last().map_or(open, |close| *close).saturating_add(1);
+        brackets.extend(found);
+    }
+    brackets
+}
+
+// the brackets of the type arguments opened at `open`, empty when they
+// follow no name or hold more than names
+fn type_arguments(chars: &[char], open: usize) -> Vec<usize> {
+    let before = chars.get(..open).and_then(|before| before.iter().rev().find(|c| !c.is_whitespace()));
+    let after = chars.get(open.saturating_add(1)..).and_then(|after| after.iter().find(|c| !c.is_whitespace()));
+    // `i < n` compares, type arguments start with a type, `?` or close at once
+    if !before.is_some_and(|c| is_identifier(*c)) || !after.is_some_and(|c| c.is_uppercase() || matches!(c, '?' | '>')) {
+        return Vec::new();
+    }
+    let mut brackets = Vec::new();
+    let mut depth = 0_usize;
+    for (index, c) in chars.iter().enumerate().skip(open) {
+        match c {
+            '<' => depth = depth.saturating_add(1),
+            '>' => depth = depth.saturating_sub(1),
+            // `T extends A & B` bounds, but not `a < B && c > d`
+            '&' if chars.get(index.saturating_add(1)) == Some(&'&') => return Vec::new(),
+            c if is_identifier(*c) || matches!(c, ' ' | ',' | '.' | '?' | '[' | ']' | '&') => continue,
+            _ => return Vec::new(),
+        }
+        brackets.push(index);
+        if depth == 0 {
+            return brackets;
+        }
+    }
+    Vec::new()
+}
+
+// `\n`, `\x7F`, `\u{1F600}`, `\u00e4` or `\077`
+fn escape_len(chars: &[char], index: usize) -> usize {
+    let after = index.saturating_add(1);
+    let hex_digits = |start: usize, max: usize| chars.iter().skip(start).take(max).take_while(|c| c.is_ascii_hexdigit()).count();
+    let len = match chars.get(after) {
+        None => 1,
+        Some('u') if chars.get(after.saturating_add(1)) == Some(&'{') => {
+            chars.iter().skip(after).position(|c| *c == '}').map_or(2, |close| close.saturating_add(2))
+        },
+        Some('u') => 2_usize.saturating_add(hex_digits(after.saturating_add(1), 4)),
+        Some('x') => 2_usize.saturating_add(hex_digits(after.saturating_add(1), 2)),
+        Some('0'..='7') => 1_usize.saturating_add(chars.iter().skip(after).take(3).take_while(|c| ('0'..='7').contains(*c)).count()),
+        Some(_) => 2,
+    };
+    cmp::min(len, chars.len().saturating_sub(index))
+}
+
+fn is_constant(word: &str) -> bool {
+    word.chars().count() > 1
+        && word.chars().any(char::is_uppercase)
+        && word.chars().all(|c| c.is_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+// the identifier before `index`, skipping whitespace
+fn preceding_word(chars: &[char], index: usize) -> Option<String> {
+    let end = chars.iter().take(index).rposition(|c| !c.is_whitespace())?.saturating_add(1);
+    let len = chars.iter().take(end).rev().take_while(|c| is_identifier(**c)).count();
+    (len > 0).then(|| chars.iter().skip(end.saturating_sub(len)).take(len).collect())
+}
+
+fn comment_type(comment: &BlockComment) -> highlighting::Type {
+    if comment.doc {
+        highlighting::Type::DocComment
+    } else {
+        highlighting::Type::MultipleComments
+    }
+}
+
+fn starts_with_at(chars: &[char], index: usize, token: &str) -> bool {
+    !token.is_empty() && token.chars().enumerate().all(|(i, c)| chars.get(index.saturating_add(i)) == Some(&c))
+}
+
+// end of the numeric literal starting at `start`: digits, radix prefix,
+// separators, fraction, exponent and type suffix
+fn number_end(chars: &[char], start: usize, rules: &NumberRules) -> usize {
+    let mut index = start;
+    let mut hex = false;
+    if let Some(prefix) = rules.prefixes().iter().find(|prefix| starts_with_at(chars, index, prefix)) {
+        index = index.saturating_add(prefix.chars().count());
+        hex = prefix.ends_with(['x', 'X']);
+    }
+    let is_digit = |c: char| if hex { c.is_ascii_hexdigit() } else { c.is_ascii_digit() } || Some(c) == rules.separator();
+    while let Some(c) = chars.get(index) {
+        let next = chars.get(index.saturating_add(1));
+        if is_digit(*c) || (!hex && *c == '.' && next.is_some_and(char::is_ascii_digit)) {
+            index = index.saturating_add(1);
+        } else if !hex && rules.exponent() && (*c == 'e' || *c == 'E') {
+            let sign = usize::from(matches!(next, Some('+' | '-')));
+            if !chars.get(index.saturating_add(1 + sign)).is_some_and(char::is_ascii_digit) {
+                break;
+            }
+            index = index.saturating_add(1 + sign);
+        } else {
+            break;
+        }
+    }
+    if let Some(suffix) = rules.suffixes().iter()
+        .filter(|suffix| starts_with_at(chars, index, suffix))
+        .map(|suffix| suffix.chars().count())
+        .filter(|len| chars.get(index.saturating_add(*len)).is_none_or(|c| is_separators(*c)))
+        .max() {
+        index = index.saturating_add(suffix);
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Row;
+    use crate::filetype::FileType;
+    use crate::highlighting::{State, Type};
+
+    // rows of `lines` highlighted one after the other, as a document does
+    fn lex(language: &str, lines: &[&str]) -> Vec<Row> {
+        let file_type = FileType::by_name(language).expect("builtin language");
+        let mut state = State::Normal;
+        lines.iter().map(|line| {
+            let mut row = Row::from(*line);
+            state = row.highlight(file_type.highlighting_options(), state.clone());
+            row
+        }).collect()
+    }
+
+    fn lex_row(language: &str, line: &str) -> Row {
+        lex(language, &[line]).remove(0)
+    }
+
+    // the type of each char of the first `part` in `row`
+    fn types(row: &Row, part: &str) -> Vec<Type> {
+        let byte_index = row.as_str().find(part).expect("part in row");
+        let start = row.as_str().get(..byte_index).unwrap_or_default().chars().count();
+        (start..start.saturating_add(part.chars().count()))
+            .map(|index| row.highlighting_at(index).unwrap_or(Type::None))
+            .collect()
+    }
+
+    fn assert_span(row: &Row, part: &str, hl_type: Type) {
+        assert_eq!(types(row, part), vec![hl_type; part.chars().count()], "{part:?} in {:?}", row.as_str());
+    }
+
+    #[test]
+    fn rust_escaped_quotes_stay_in_the_string() {
+        let row = lex_row("Rust", r#"let s = "quote \" and \\"; x"#);
+        assert_eq!(types(&row, r#""quote "#), vec![Type::String; 7]);
+        assert_span(&row, r#"\""#, Type::Escape);
+        assert_span(&row, r"\\", Type::Escape);
+        assert_eq!(types(&row, r#""; x"#), vec![Type::String, Type::Punctuation, Type::None, Type::None]);
+    }
+
+    #[test]
+    fn rust_raw_strings() {
+        let row = lex_row("Rust", r###"let a = r#"a "quoted" word"#; let b = br#"raw "bytes""#;"###);
+        assert_span(&row, r###"r#"a "quoted" word"#"###, Type::String);
+        assert_span(&row, r###"br#"raw "bytes""#"###, Type::String);
+    }
+
+    #[test]
+    fn rust_raw_string_continues_on_the_next_row() {
+        let rows = lex("Rust", &[r###"let a = r##"ends with "# only"###, r###"after "##;"###]);
+        assert_span(&rows[1], r###"after "##"###, Type::String);
+        assert_span(&rows[1], ";", Type::Punctuation);
+    }
+
+    #[test]
+    fn rust_byte_characters() {
+        let row = lex_row("Rust", "let x = b'x';");
+        assert_span(&row, "b'x'", Type::Character);
+    }
+
+    #[test]
+    fn rust_lifetimes_and_characters() {
+        let row = lex_row("Rust", "fn f<'a>(c: &'a str) -> char { 'a' }");
+        assert_eq!(types(&row, "'a>"), vec![Type::Lifetime, Type::Lifetime, Type::Operator]);
+        assert_eq!(types(&row, "&'a "), vec![Type::Operator, Type::Lifetime, Type::Lifetime, Type::None]);
+        assert_span(&row, "'a'", Type::Character);
+    }
+
+    #[test]
+    fn rust_nested_block_comments() {
+        let row = lex_row("Rust", "/* outer /* inner */ still outer */ x");
+        assert_span(&row, "/* outer /* inner */ still outer */", Type::MultipleComments);
+        assert_eq!(types(&row, " x"), vec![Type::None; 2]);
+    }
+
+    #[test]
+    fn rust_nested_block_comment_spans_rows() {
+        let rows = lex("Rust", &["/* outer /* inner */", "still outer */ x"]);
+        assert_span(&rows[1], "still outer */", Type::MultipleComments);
+        assert_eq!(types(&rows[1], " x"), vec![Type::None; 2]);
+    }
+
+    #[test]
+    fn rust_doc_comments() {
+        let rows = lex("Rust", &["/// outer doc", "//! inner doc", "// plain", "/** block doc */"]);
+        assert_span(&rows[0], "/// outer doc", Type::DocComment);
+        assert_span(&rows[1], "//! inner doc", Type::DocComment);
+        assert_span(&rows[2], "// plain", Type::Comment);
+        assert_span(&rows[3], "/** block doc */", Type::DocComment);
+    }
+
+    #[test]
+    fn rust_attributes() {
+        let rows = lex("Rust", &["#[cfg(all(feature = \"a\", not(test)))]", "#![allow(dead_code)]"]);
+        assert_span(&rows[0], "#[cfg(all(feature = ", Type::Attribute);
+        assert_span(&rows[0], ", not(test)))]", Type::Attribute);
+        assert_span(&rows[1], "#![allow(dead_code)]", Type::Attribute);
+    }
+
+    #[test]
+    fn rust_macros() {
+        let row = lex_row("Rust", "println!(\"{x}\"); a != b");
+        assert_span(&row, "println!", Type::Macro);
+        assert_span(&row, "!=", Type::Operator);
+    }
+
+    #[test]
+    fn rust_numbers_with_suffixes() {
+        let row = lex_row("Rust", "[0xFF_u8, 1e-3f64, 1_000_000.0]");
+        assert_span(&row, "0xFF_u8", Type::Number);
+        assert_span(&row, "1e-3f64", Type::Number);
+        assert_span(&row, "1_000_000.0", Type::Number);
+    }
+
+    #[test]
+    fn rust_ranges_are_not_floats() {
+        let row = lex_row("Rust", "let r = 1..10;");
+        assert_span(&row, "1", Type::Number);
+        assert_span(&row, "..", Type::Punctuation);
+        assert_span(&row, "10", Type::Number);
+    }
+
+    #[test]
+    fn java_annotations() {
+        let row = lex_row("Java", "@Override public void run() {}");
+        assert_span(&row, "@Override", Type::Attribute);
+        assert_span(&row, "public", Type::PrimaryKeywords);
+    }
+
+    #[test]
+    fn java_text_blocks_span_rows() {
+        let rows = lex("Java", &["String s = \"\"\"", "    a \"quoted\" line", "    \"\"\"; int x;"]);
+        assert_span(&rows[0], "\"\"\"", Type::String);
+        assert_span(&rows[1], "    a ", Type::String);
+        assert_span(&rows[1], "\"quoted\" line", Type::String);
+        assert_span(&rows[2], "    \"\"\"", Type::String);
+        assert_span(&rows[2], ";", Type::Punctuation);
+        assert_span(&rows[2], "int", Type::SecondaryKeywords);
+    }
+
+    #[test]
+    fn java_unicode_character_escape() {
+        let row = lex_row("Java", "char c = '\\u0041';");
+        assert!(types(&row, "'\\u0041'").iter().all(|hl_type| matches!(hl_type, Type::Character | Type::Escape)));
+        assert_span(&row, ";", Type::Punctuation);
+    }
+
+    #[test]
+    fn java_number_suffixes_and_prefixes() {
+        let row = lex_row("Java", "long a = 10L; float b = 1.5f; int c = 0b1010; long d = 0xFFL;");
+        assert_span(&row, "10L", Type::Number);
+        assert_span(&row, "1.5f", Type::Number);
+        assert_span(&row, "0b1010", Type::Number);
+        assert_span(&row, "0xFFL", Type::Number);
+    }
+
+    #[test]
+    fn java_keywords() {
+        let rows = lex("Java", &[
+            "public sealed interface Shape permits Circle {}",
+            "record Circle(double r) implements Shape {}",
+            "void f() throws Exception { try { var x = 1; } catch (Exception e) {} }",
+        ]);
+        for (row, keyword) in [(0, "sealed"), (0, "permits"), (1, "record"), (1, "implements"), (2, "throws"), (2, "try"), (2, "var"), (2, "catch")] {
+            assert_span(&rows[row], keyword, Type::PrimaryKeywords);
+        }
+    }
+
+    #[test]
+    fn java_generics_are_punctuation() {
+        let row = lex_row("Java", "Map<String, List<Integer>> m = new HashMap<>();");
+        assert_span(&row, "<", Type::Punctuation);
+        assert_span(&row, ">>", Type::Punctuation);
+        assert_span(&row, "<>", Type::Punctuation);
+        assert_span(&row, "String", Type::SecondaryKeywords);
+        assert_span(&row, "List", Type::Class);
+    }
+
+    #[test]
+    fn java_generic_methods_and_wildcards() {
+        let row = lex_row("Java", "static <T extends Comparable<? super T>> T max(List<? extends T> xs)");
+        assert_eq!(types(&row, "<T"), vec![Type::Punctuation, Type::Class]);
+        assert_eq!(types(&row, "<? super T>>"), vec![
+            Type::Punctuation, Type::Operator, Type::None, Type::PrimaryKeywords, Type::PrimaryKeywords, Type::PrimaryKeywords,
+            Type::PrimaryKeywords, Type::PrimaryKeywords, Type::None, Type::Class, Type::Punctuation, Type::Punctuation,
+        ]);
+        assert_eq!(types(&row, "T> xs"), vec![Type::Class, Type::Punctuation, Type::None, Type::None, Type::None]);
+    }
+
+    #[test]
+    fn java_comparisons_stay_operators() {
+        let row = lex_row("Java", "if (i < n && a > b || x<MAX && y>z) {}");
+        assert_span(&row, "<", Type::Operator);
+        assert_span(&row, ">", Type::Operator);
+        assert_eq!(types(&row, "x<MAX"), vec![Type::None, Type::Operator, Type::Constant, Type::Constant, Type::Constant]);
+    }
+
+    #[test]
+    fn python_triple_quoted_strings_span_rows() {
+        let rows = lex("Python", &["x = '''it's", "still \"in\" ''' + 1"]);
+        assert_span(&rows[0], "'''it's", Type::String);
+        assert_span(&rows[1], "still \"in\" '''", Type::String);
+        assert_span(&rows[1], "1", Type::Number);
+    }
+
+    #[test]
+    fn python_doc_strings() {
+        let rows = lex("Python", &["def f():", "    \"\"\"Doc string.", "", "    More.\"\"\"", "    return None"]);
+        assert_span(&rows[1], "\"\"\"Doc string.", Type::String);
+        assert_span(&rows[3], "    More.\"\"\"", Type::String);
+        assert_span(&rows[4], "return", Type::PrimaryKeywords);
+    }
+
+    #[test]
+    fn python_string_prefixes() {
+        let row = lex_row("Python", "a = rb'x' + f\"{y}\" + bf(1)");
+        assert_span(&row, "rb'x'", Type::String);
+        assert_span(&row, "f\"{y}\"", Type::String);
+        assert_eq!(types(&row, "bf("), vec![Type::None, Type::None, Type::None]);
+    }
+
+    #[test]
+    fn javascript_template_literal_interpolation() {
+        let row = lex_row("JavaScript", "const s = `a ${b} c` + 1;");
+        assert_span(&row, "`a ", Type::String);
+        assert_span(&row, "${b}", Type::Variable);
+        assert_span(&row, " c`", Type::String);
+        assert_span(&row, "1", Type::Number);
+    }
+
+    #[test]
+    fn javascript_nested_template_literal_spans_rows() {
+        let rows = lex("JavaScript", &["const s = `a ${b ? `${c}` : {}} d", "e ${f} g`;"]);
+        assert_span(&rows[0], "${b ? `${c}` : {}}", Type::Variable);
+        assert_span(&rows[0], " d", Type::String);
+        assert_span(&rows[1], "e ", Type::String);
+        assert_span(&rows[1], "${f}", Type::Variable);
+        assert_span(&rows[1], " g`", Type::String);
+        assert_span(&rows[1], ";", Type::None);
+    }
+
+    #[test]
+    fn typescript_template_literals() {
+        let row = lex_row("TypeScript", "let s: string = `x ${y}`;");
+        assert_span(&row, "string", Type::SecondaryKeywords);
+        assert_span(&row, "${y}", Type::Variable);
+        assert_span(&row, "`x ", Type::String);
+    }
+
+    #[test]
+    fn shell_heredocs() {
+        let rows = lex("Shell", &["cat <<EOF > out", "$HOME # not a comment", "EOF", "echo done"]);
+        assert_span(&rows[0], "<<EOF", Type::String);
+        assert_span(&rows[0], " > out", Type::None);
+        assert_span(&rows[1], "$HOME # not a comment", Type::String);
+        assert_span(&rows[2], "EOF", Type::String);
+        assert_span(&rows[3], "echo", Type::SecondaryKeywords);
+    }
+
+    #[test]
+    fn shell_quoted_and_indented_heredocs() {
+        let rows = lex("Shell", &["cat <<-'END'", "\tEND later", "\tEND", "done"]);
+        assert_span(&rows[0], "<<-'END'", Type::String);
+        assert_span(&rows[1], "\tEND later", Type::String);
+        assert_span(&rows[2], "\tEND", Type::String);
+        assert_span(&rows[3], "done", Type::PrimaryKeywords);
+    }
+
+    #[test]
+    fn json_keys_and_values() {
+        let row = lex_row("JSON", "{\"key\": \"value\", \"n\": 1, \"t\": true}");
+        assert_span(&row, "\"key\"", Type::Key);
+        assert_span(&row, "\"value\"", Type::String);
+        assert_span(&row, "\"n\"", Type::Key);
+        assert_span(&row, "1", Type::Number);
+        assert_span(&row, "true", Type::PrimaryKeywords);
+    }
+
+    #[test]
+    fn toml_table_headers_and_keys() {
+        let rows = lex("TOML", &["[package.metadata]", "[[bin]]", "a.b = \"[not a table]\" # note"]);
+        assert_span(&rows[0], "[package.metadata]", Type::Heading);
+        assert_span(&rows[1], "[[bin]]", Type::Heading);
+        assert_span(&rows[2], "a.b", Type::Key);
+        assert_span(&rows[2], "\"[not a table]\"", Type::String);
+        assert_span(&rows[2], "# note", Type::Comment);
+    }
+
+    #[test]
+    fn yaml_anchors_and_merge_keys() {
+        let rows = lex("YAML", &["base: &base", "  - item: 1", "child:", "  <<: *base"]);
+        assert_span(&rows[0], "base", Type::Key);
+        assert_span(&rows[0], "&base", Type::Anchor);
+        assert_span(&rows[1], "item", Type::Key);
+        assert_span(&rows[3], "<<", Type::Key);
+        assert_span(&rows[3], "*base", Type::Anchor);
+    }
+
+    #[test]
+    fn yaml_urls_are_not_keys() {
+        let row = lex_row("YAML", "url: http://example.com:80");
+        assert_span(&row, "url", Type::Key);
+        assert_span(&row, "http://example.com:", Type::None);
+    }
+
+    #[test]
+    fn markdown_headings() {
+        let rows = lex("Markdown", &["# Title", "### Sub *section*", "not # a heading"]);
+        assert_span(&rows[0], "# Title", Type::Heading);
+        assert_span(&rows[1], "### Sub *section*", Type::Heading);
+        assert_span(&rows[2], "not # a heading", Type::None);
+    }
+
+    #[test]
+    fn markdown_emphasis_and_code_spans() {
+        let row = lex_row("Markdown", "some *em*, **strong** and `co*de` here");
+        assert_span(&row, "*em*", Type::Emphasis);
+        assert_span(&row, "**strong**", Type::Emphasis);
+        assert_span(&row, "`co*de`", Type::Code);
+        assert_span(&row, " here", Type::None);
+    }
+
+    #[test]
+    fn markdown_fenced_code_blocks_span_rows() {
+        let rows = lex("Markdown", &["```rust", "let *x* = 1;", "```", "# After"]);
+        assert_span(&rows[0], "```rust", Type::Code);
+        assert_span(&rows[1], "let *x* = 1;", Type::Code);
+        assert_span(&rows[2], "```", Type::Code);
+        assert_span(&rows[3], "# After", Type::Heading);
+    }
+
+    #[test]
+    fn shell_expansions() {
+        let row = lex_row("Shell", "echo $HOME ${PATH%:*} $(pwd) $((1 + 2)) $1");
+        for expansion in ["$HOME", "${PATH%:*}", "$(pwd)", "$((1 + 2))", "$1"] {
+            assert_span(&row, expansion, Type::Variable);
+        }
+    }
+
+    #[test]
+    fn shell_expansions_in_double_quotes() {
+        let row = lex_row("Shell", "echo \"in $(pwd) as ${USER}\" '$(not)'");
+        assert_span(&row, "$(pwd)", Type::Variable);
+        assert_span(&row, "${USER}", Type::Variable);
+        assert_span(&row, "'$(not)'", Type::String);
+    }
+}
@@ -0,0 +1,142 @@
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Parser, Point, Query, QueryCursor, Tree};
+use crate::{Position, Row};
+use crate::highlighting;
+
+// tree-sitter backend, used instead of the keyword highlighter when the
+// filetype names a grammar compiled into heitx
+pub struct Syntax {
+    parser: Parser,
+    query: Query,
+    // highlighting type of every capture name in `query`
+    capture_types: Vec<highlighting::Type>,
+    tree: Option<Tree>,
+    // rows joined by '\n', what `tree` was parsed from
+    source: Vec<u8>,
+    stale: bool,
+}
+
+impl Syntax {
+    pub fn new(grammar: &str) -> Option<Self> {
+        let (language, highlights): (tree_sitter::Language, &str) = match grammar {
+            "rust" => (tree_sitter_rust::LANGUAGE.into(), tree_sitter_rust::HIGHLIGHTS_QUERY),
+            "java" => (tree_sitter_java::LANGUAGE.into(), tree_sitter_java::HIGHLIGHTS_QUERY),
+            _ => return None,
+        };
+        let mut parser = Parser::new();
+        parser.set_language(&language).ok()?;
+        let query = Query::new(&language, highlights).ok()?;
+        let capture_types = query.capture_names().iter().map(|name| capture_type(name)).collect();
+        Some(Self {
+            parser,
+            query,
+            capture_types,
+            tree: None,
+            source: Vec::new(),
+            stale: true,
+        })
+    }
+
+    // must be called before the rows are modified
+    pub fn edit(&mut self, rows: &[Row], at: &Position, removed: &str, inserted: &str) {
+        self.stale = true;
+        let Some(tree) = &mut self.tree else {
+            return;
+        };
+        let column = rows.get(at.y).map_or(0, |row| row.byte_index(at.x));
+        let start_byte = rows.iter()
+            .take(at.y)
+            .map(|row| row.as_bytes().len().saturating_add(1))
+            .sum::<usize>()
+            .saturating_add(column);
+        let start_position = Point::new(at.y, column);
+        tree.edit(&InputEdit {
+            start_byte,
+            old_end_byte: start_byte.saturating_add(removed.len()),
+            new_end_byte: start_byte.saturating_add(inserted.len()),
+            start_position,
+            old_end_position: end_point(start_position, removed),
+            new_end_position: end_point(start_position, inserted),
+        });
+    }
+
+    fn parse(&mut self, rows: &[Row]) {
+        self.source.clear();
+        for row in rows {
+            self.source.extend_from_slice(row.as_bytes());
+            self.source.push(b'\n');
+        }
+        // the edited old tree lets tree-sitter reuse unchanged subtrees
+        self.tree = self.parser.parse(&self.source, self.tree.as_ref());
+        self.stale = false;
+    }
+
+    pub fn highlight(&mut self, rows: &mut [Row], start: usize, end: usize) {
+        if self.stale {
+            self.parse(rows);
+        }
+        let Some(tree) = &self.tree else {
+            return;
+        };
+        let start = start.min(end);
+        let mut spans: Vec<Vec<(usize, usize, highlighting::Type)>> = vec![Vec::new(); end.saturating_sub(start)];
+        let mut cursor = QueryCursor::new();
+        cursor.set_point_range(Point::new(start, 0)..Point::new(end, 0));
+        let mut captures = cursor.captures(&self.query, tree.root_node(), self.source.as_slice());
+        let mut last_node = None;
+        while let Some((query_match, capture_index)) = captures.next() {
+            let Some(capture) = query_match.captures.get(*capture_index) else {
+                continue;
+            };
+            // the first pattern that captures a node wins
+            if last_node == Some(capture.node.id()) {
+                continue;
+            }
+            last_node = Some(capture.node.id());
+            let hl_type = self.capture_types.get(capture.index as usize).copied().unwrap_or(highlighting::Type::None);
+            let (node_start, node_end) = (capture.node.start_position(), capture.node.end_position());
+            for y in node_start.row.max(start)..=node_end.row.min(end.saturating_sub(1)) {
+                let from = if y == node_start.row { node_start.column } else { 0 };
+                let to = if y == node_end.row { node_end.column } else { usize::MAX };
+                if let Some(row_spans) = spans.get_mut(y.saturating_sub(start)) {
+                    row_spans.push((from, to, hl_type));
+                }
+            }
+        }
+        for (row, row_spans) in rows.iter_mut().skip(start).zip(spans) {
+            row.set_highlighting(&row_spans);
+        }
+    }
+}
+
+fn end_point(start: Point, text: &str) -> Point {
+    match text.rfind('\n') {
+        Some(index) => Point::new(
+            start.row.saturating_add(text.matches('\n').count()),
+            text.len().saturating_sub(index).saturating_sub(1),
+        ),
+        None => Point::new(start.row, start.column.saturating_add(text.len())),
+    }
+}
+
+fn capture_type(name: &str) -> highlighting::Type {
+    match name {
+        "constant.builtin" | "number" => highlighting::Type::Number,
+        "comment" => highlighting::Type::Comment,
+        "comment.documentation" => highlighting::Type::DocComment,
+        "attribute" => highlighting::Type::Attribute,
+        "function.macro" => highlighting::Type::Macro,
+        "label" => highlighting::Type::Lifetime,
+        "keyword" => highlighting::Type::PrimaryKeywords,
+        "type.builtin" => highlighting::Type::SecondaryKeywords,
+        "type" | "constructor" => highlighting::Type::Class,
+        "constant" => highlighting::Type::Constant,
+        "function" | "function.method" | "function.builtin" => highlighting::Type::Function,
+        "operator" => highlighting::Type::Operator,
+        "escape" => highlighting::Type::Escape,
+        "character" => highlighting::Type::Character,
+        _ if name.starts_with("punctuation") => highlighting::Type::Punctuation,
+        _ if name.starts_with("string") => highlighting::Type::String,
+        _ => highlighting::Type::None,
+    }
+}
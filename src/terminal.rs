@@ -1,7 +1,11 @@
 
 use std::io::{self, stdout, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
 use termion::color;
-use termion::event::Key;
+use termion::event::{Event, Key};
 use termion::input::TermRead;
 use termion::raw::{IntoRawMode, RawTerminal};
 use crate::Position;
@@ -17,6 +21,7 @@ pub struct Terminal {
 }
 
 impl Terminal {
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Result<Self, std::io::Error> {
         let size = termion::terminal_size()?;
         Ok(Self {
@@ -50,10 +55,17 @@ impl Terminal {
     }
 
     pub fn read_key() -> Result<Key, std::io::Error> {
-        loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
-            }
+        keys().lock().map_err(|_| io::Error::other("key reader failed"))?
+            .recv()
+            .map_err(|_| io::Error::from(io::ErrorKind::UnexpectedEof))?
+    }
+
+    // None when no key was pressed within `timeout`
+    pub fn read_key_timeout(timeout: Duration) -> Result<Option<Key>, std::io::Error> {
+        match keys().lock().map_err(|_| io::Error::other("key reader failed"))?.recv_timeout(timeout) {
+            Ok(key) => key.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
         }
     }
 
@@ -69,7 +81,7 @@ impl Terminal {
         print!("{}", termion::clear::CurrentLine);
     }
 
-    pub fn set_bg_color(color: color::LightCyan) {
+    pub fn set_bg_color<C: color::Color>(color: C) {
         print!("{}", color::Bg(color));
     }
 
@@ -77,11 +89,36 @@ impl Terminal {
         print!("{}", color::Bg(color::Reset));
     }
 
-    pub fn set_fg_color(color: color::LightBlack) {
+    pub fn set_fg_color<C: color::Color>(color: C) {
         print!("{}", color::Fg(color));
     }
 
     pub fn reset_fg_color() {
         print!("{}", color::Fg(color::Reset));
     }
+}
+
+// keys are read in a thread of their own, so waiting for one can time out
+// while something else is going on
+fn keys() -> &'static Mutex<Receiver<Result<Key, io::Error>>> {
+    static KEYS: OnceLock<Mutex<Receiver<Result<Key, io::Error>>>> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for event in io::stdin().events() {
+                let key = match event {
+                    Ok(Event::Key(key)) => Ok(key),
+                    // termion does not parse Ctrl-Delete, it reads as Alt-d
+                    Ok(Event::Unsupported(bytes)) if bytes == b"\x1b[3;5~" => Ok(Key::Alt('d')),
+                    Ok(_) => continue,
+                    Err(error) => Err(error),
+                };
+                // the editor is gone
+                if sender.send(key).is_err() {
+                    return;
+                }
+            }
+        });
+        Mutex::new(receiver)
+    })
 }
\ No newline at end of file
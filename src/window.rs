@@ -0,0 +1,182 @@
+use std::cmp;
+use crate::Position;
+
+const MIN_PERCENT: u16 = 10;
+const MAX_PERCENT: u16 = 90;
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum Split {
+    // windows stacked on top of each other
+    Horizontal,
+    // windows side by side, with a separator column between them
+    Vertical,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// what a window shows, the editor keeps the focused window's in its own fields
+#[derive(Default, Clone)]
+pub struct View {
+    pub buffer: usize,
+    pub cursor_position: Position,
+    pub offset: Position,
+    pub selection_anchor: Option<Position>,
+}
+
+// windows are numbered by their index in the editor's window list
+pub enum Layout {
+    Window(usize),
+    Split {
+        split: Split,
+        // share of the area the first half gets
+        percent: u16,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    // rects of every window in `area`, plus the separator columns of
+    // vertical splits
+    pub fn rects(&self, area: Rect, windows: &mut Vec<(usize, Rect)>, separators: &mut Vec<Rect>) {
+        match self {
+            Self::Window(window) => windows.push((*window, area)),
+            Self::Split { split: Split::Horizontal, percent, first, second } => {
+                let height = share(area.height, *percent);
+                first.rects(Rect { height, ..area }, windows, separators);
+                second.rects(Rect {
+                    y: area.y.saturating_add(height),
+                    height: area.height.saturating_sub(height),
+                    ..area
+                }, windows, separators);
+            },
+            Self::Split { split: Split::Vertical, percent, first, second } => {
+                let width = share(area.width.saturating_sub(1), *percent);
+                first.rects(Rect { width, ..area }, windows, separators);
+                separators.push(Rect { x: area.x.saturating_add(width), width: 1, ..area });
+                second.rects(Rect {
+                    x: area.x.saturating_add(width).saturating_add(1),
+                    width: area.width.saturating_sub(width).saturating_sub(1),
+                    ..area
+                }, windows, separators);
+            },
+        }
+    }
+
+    // puts `new_window` next to `window`, false when `window` is not in the layout
+    pub fn split(&mut self, window: usize, new_window: usize, split: Split) -> bool {
+        match self {
+            Self::Window(id) if *id == window => {
+                *self = Self::Split {
+                    split,
+                    percent: 50,
+                    first: Box::new(Self::Window(window)),
+                    second: Box::new(Self::Window(new_window)),
+                };
+                true
+            },
+            Self::Window(_) => false,
+            Self::Split { first, second, .. } => first.split(window, new_window, split) || second.split(window, new_window, split),
+        }
+    }
+
+    // gives the space of `window` to its sibling and renumbers the windows
+    // after it, false for the last window
+    pub fn remove(&mut self, window: usize) -> bool {
+        let Self::Split { first, second, .. } = self else {
+            return false;
+        };
+        let sibling = match (&**first, &**second) {
+            (Self::Window(id), _) if *id == window => Some(mem_take(second)),
+            (_, Self::Window(id)) if *id == window => Some(mem_take(first)),
+            _ => None,
+        };
+        let removed = if let Some(sibling) = sibling {
+            *self = sibling;
+            true
+        } else {
+            first.remove(window) || second.remove(window)
+        };
+        if removed {
+            self.renumber(window);
+        }
+        removed
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Self::Window(id) if *id > removed => *id = id.saturating_sub(1),
+            Self::Window(_) => (),
+            Self::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            },
+        }
+    }
+
+    // grows `window` by `delta` percent of the innermost split holding it
+    pub fn resize(&mut self, window: usize, delta: i16) -> bool {
+        let Self::Split { percent, first, second, .. } = self else {
+            return false;
+        };
+        if first.resize(window, delta) || second.resize(window, delta) {
+            return true;
+        }
+        let delta = if first.contains(window) {
+            delta
+        } else if second.contains(window) {
+            delta.saturating_neg()
+        } else {
+            return false;
+        };
+        *percent = percent.saturating_add_signed(delta).clamp(MIN_PERCENT, MAX_PERCENT);
+        true
+    }
+
+    fn contains(&self, window: usize) -> bool {
+        match self {
+            Self::Window(id) => *id == window,
+            Self::Split { first, second, .. } => first.contains(window) || second.contains(window),
+        }
+    }
+}
+
+// the window nearest to `from` in `direction` among those beside it
+pub fn neighbour(rects: &[(usize, Rect)], from: Rect, direction: Direction) -> Option<usize> {
+    rects.iter()
+        .filter(|(_, rect)| match direction {
+            Direction::Left => rect.x.saturating_add(rect.width) <= from.x && overlaps(rect.y, rect.height, from.y, from.height),
+            Direction::Right => rect.x >= from.x.saturating_add(from.width) && overlaps(rect.y, rect.height, from.y, from.height),
+            Direction::Up => rect.y.saturating_add(rect.height) <= from.y && overlaps(rect.x, rect.width, from.x, from.width),
+            Direction::Down => rect.y >= from.y.saturating_add(from.height) && overlaps(rect.x, rect.width, from.x, from.width),
+        })
+        .min_by_key(|(_, rect)| rect.x.abs_diff(from.x).saturating_add(rect.y.abs_diff(from.y)))
+        .map(|(window, _)| *window)
+}
+
+fn overlaps(start: usize, len: usize, other_start: usize, other_len: usize) -> bool {
+    start < other_start.saturating_add(other_len) && other_start < start.saturating_add(len)
+}
+
+#[allow(clippy::integer_division)]
+fn share(len: usize, percent: u16) -> usize {
+    cmp::min(len.saturating_mul(usize::from(percent)) / 100, len)
+}
+
+fn mem_take(layout: &mut Box<Layout>) -> Layout {
+    std::mem::replace(&mut **layout, Layout::Window(0))
+}